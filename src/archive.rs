@@ -0,0 +1,532 @@
+use crate::{
+    platform::Platform,
+    registry::{ImageConfig, Manifest, ManifestList, PlatformManifest, Registry},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// Transport prefix of `docker save` tarballs (e.g. "docker-archive:image.tar[:name:tag]")
+const DOCKER_ARCHIVE: &str = "docker-archive:";
+
+/// Transport prefix of OCI image layout directories (e.g. "oci:directory[:tag]")
+const OCI_LAYOUT: &str = "oci:";
+
+/// Annotation naming the tag of an image in an OCI layout index
+const REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// Annotation containerd adds with the full image name to the indexes it exports
+const CONTAINERD_NAME: &str = "io.containerd.image.name";
+
+/// An image stored on disk
+#[derive(Debug, PartialEq)]
+pub enum Archive {
+    Docker {
+        path: PathBuf,
+        reference: Option<String>,
+    },
+    Oci {
+        path: PathBuf,
+        reference: Option<String>,
+    },
+}
+
+/// Labels of an image read from an archive and the name it was saved under
+#[derive(Debug, PartialEq)]
+pub struct ArchiveImage {
+    pub labels: HashMap<String, String>,
+    pub name: Option<String>,
+}
+
+/// An entry of the `manifest.json` written by `docker save`
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SavedImage {
+    config: String,
+    repo_tags: Option<Vec<String>>,
+}
+
+impl Archive {
+    /// Parse an archive input, `None` if it is an image reference
+    pub fn parse(input: &str) -> Option<Self> {
+        let split = |rest: &str| match rest.split_once(':') {
+            Some((path, reference)) => (PathBuf::from(path), Some(reference.to_string())),
+            None => (PathBuf::from(rest), None),
+        };
+
+        if let Some(rest) = input.strip_prefix(DOCKER_ARCHIVE) {
+            let (path, reference) = split(rest);
+            Some(Self::Docker { path, reference })
+        } else if let Some(rest) = input.strip_prefix(OCI_LAYOUT) {
+            let (path, reference) = split(rest);
+            Some(Self::Oci { path, reference })
+        } else {
+            None
+        }
+    }
+
+    /// Read the labels of the image, selecting the platform from multi-platform layouts
+    pub fn inspect(&self, platform: &Platform) -> Result<ArchiveImage> {
+        match self {
+            Self::Docker { path, reference } => Self::docker(path, reference.as_deref()),
+            Self::Oci { path, reference } => Self::oci(path, reference.as_deref(), platform),
+        }
+    }
+
+    fn docker(path: &Path, reference: Option<&str>) -> Result<ArchiveImage> {
+        let images: Vec<SavedImage> = serde_json::from_slice(&Self::entry(path, "manifest.json")?)?;
+
+        let image = match reference {
+            Some(reference) => images
+                .iter()
+                .find(|image| image.repo_tags.iter().flatten().any(|tag| tag == reference))
+                .ok_or_else(|| anyhow!("{} has no image {reference}", path.display()))?,
+            None => match images.as_slice() {
+                [image] => image,
+                [] => bail!("{} has no images", path.display()),
+                _ => bail!(
+                    "{} has several images, add the image to use (e.g. docker-archive:{}:name:tag)",
+                    path.display(),
+                    path.display()
+                ),
+            },
+        };
+
+        let config: ImageConfig = serde_json::from_slice(&Self::entry(path, &image.config)?)?;
+
+        Ok(ArchiveImage {
+            labels: config.config.labels.unwrap_or_default(),
+            name: reference
+                .map(String::from)
+                .or_else(|| image.repo_tags.iter().flatten().next().cloned()),
+        })
+    }
+
+    fn oci(path: &Path, reference: Option<&str>, platform: &Platform) -> Result<ArchiveImage> {
+        if !path.join("oci-layout").is_file() {
+            bail!("{} is not an OCI image layout", path.display());
+        }
+
+        let index: ManifestList = serde_json::from_slice(&fs::read(path.join("index.json"))?)?;
+
+        let descriptor = match reference {
+            Some(reference) => index
+                .manifests
+                .iter()
+                .find(|manifest| Self::ref_name(manifest).is_some_and(|name| name == reference))
+                .ok_or_else(|| anyhow!("{} has no image tagged {reference}", path.display()))?,
+            None => match index.manifests.as_slice() {
+                [manifest] => manifest,
+                // A multi-platform image can be listed at the top of the index itself
+                manifests if manifests.iter().all(|manifest| manifest.platform.is_some()) => {
+                    Registry::select(manifests, platform)?
+                }
+                manifests => {
+                    let tags: Vec<&str> = manifests.iter().filter_map(Self::ref_name).collect();
+                    bail!(
+                        "{} has several images, add the tag to use (available: {})",
+                        path.display(),
+                        tags.join(", ")
+                    );
+                }
+            },
+        };
+
+        let name = descriptor
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(CONTAINERD_NAME))
+            .cloned();
+
+        let blob = Self::blob(path, &descriptor.digest)?;
+        let list: ManifestList = serde_json::from_slice(&blob)?;
+
+        // Multi-platform images point at a nested index with a manifest per platform
+        let (manifest, mut annotations) = if Registry::index("", list.media_type.as_deref()) {
+            let digest = &Registry::select(&list.manifests, platform)?.digest;
+            let manifest: Manifest = serde_json::from_slice(&Self::blob(path, digest)?)?;
+            (manifest, list.annotations.unwrap_or_default())
+        } else {
+            (serde_json::from_slice::<Manifest>(&blob)?, HashMap::new())
+        };
+
+        let config: ImageConfig =
+            serde_json::from_slice(&Self::blob(path, &manifest.config.digest)?)?;
+
+        annotations.extend(manifest.annotations.unwrap_or_default());
+        annotations.extend(config.config.labels.unwrap_or_default());
+
+        Ok(ArchiveImage {
+            labels: annotations,
+            name,
+        })
+    }
+
+    fn ref_name(manifest: &PlatformManifest) -> Option<&str> {
+        manifest
+            .annotations
+            .as_ref()?
+            .get(REF_NAME)
+            .map(String::as_str)
+    }
+
+    /// Read a blob of an OCI layout by its digest (e.g. "sha256:<hex>")
+    fn blob(layout: &Path, digest: &str) -> Result<Vec<u8>> {
+        let (algorithm, hex) = digest
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid digest {digest}"))?;
+
+        fs::read(Self::blob_path(layout, algorithm, hex))
+            .with_context(|| format!("Blob {digest} is missing from {}", layout.display()))
+    }
+
+    /// Read a file from a tarball which may be gzip compressed
+    fn entry(archive: &Path, name: &str) -> Result<Vec<u8>> {
+        let mut file = BufReader::new(File::open(archive)?);
+
+        let reader: Box<dyn Read> = if file.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+            Box::new(GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+
+        for entry in tar::Archive::new(reader).entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?;
+
+            if path.strip_prefix(".").unwrap_or(&path) == Path::new(name) {
+                let mut contents = vec![];
+                entry.read_to_end(&mut contents)?;
+                return Ok(contents);
+            }
+        }
+
+        bail!("{} has no {name}", archive.display())
+    }
+
+    fn blob_path(layout: &Path, algorithm: &str, hex: &str) -> PathBuf {
+        layout.join("blobs").join(algorithm).join(hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    const CONFIG: &str = r#"{
+        "architecture": "amd64",
+        "os": "linux",
+        "config": {
+            "Labels": {
+                "org.opencontainers.image.source": "https://github.com/owner/repo",
+                "org.opencontainers.image.revision": "abc"
+            }
+        }
+    }"#;
+
+    const CONFIG_DIGEST: &str =
+        "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+    const AMD64_DIGEST: &str =
+        "sha256:2222222222222222222222222222222222222222222222222222222222222222";
+    const ARM64_DIGEST: &str =
+        "sha256:3333333333333333333333333333333333333333333333333333333333333333";
+    const INDEX_DIGEST: &str =
+        "sha256:4444444444444444444444444444444444444444444444444444444444444444";
+
+    fn tarball(files: &[(&str, &str)], compress: bool) -> Result<(TempDir, PathBuf)> {
+        let directory = TempDir::new()?;
+        let path = directory.path().join("image.tar");
+
+        let mut builder = tar::Builder::new(vec![]);
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, contents.as_bytes())?;
+        }
+        let tar = builder.into_inner()?;
+
+        if compress {
+            let mut encoder = GzEncoder::new(File::create(&path)?, Compression::default());
+            encoder.write_all(&tar)?;
+            encoder.finish()?;
+        } else {
+            fs::write(&path, tar)?;
+        }
+
+        Ok((directory, path))
+    }
+
+    fn write_blob(layout: &Path, digest: &str, contents: &str) -> Result<()> {
+        let (algorithm, hex) = digest.split_once(':').unwrap();
+        fs::create_dir_all(layout.join("blobs").join(algorithm))?;
+        fs::write(Archive::blob_path(layout, algorithm, hex), contents)?;
+        Ok(())
+    }
+
+    /// OCI layout with a multi-platform image tagged "1.0"
+    fn layout() -> Result<TempDir> {
+        let layout = TempDir::new()?;
+        let path = layout.path();
+
+        fs::write(
+            path.join("oci-layout"),
+            r#"{"imageLayoutVersion": "1.0.0"}"#,
+        )?;
+        fs::write(
+            path.join("index.json"),
+            format!(
+                r#"{{
+                    "schemaVersion": 2,
+                    "manifests": [{{
+                        "mediaType": "application/vnd.oci.image.index.v1+json",
+                        "digest": "{INDEX_DIGEST}",
+                        "annotations": {{
+                            "org.opencontainers.image.ref.name": "1.0",
+                            "io.containerd.image.name": "ghcr.io/owner/repo:1.0"
+                        }}
+                    }}]
+                }}"#
+            ),
+        )?;
+
+        write_blob(
+            path,
+            INDEX_DIGEST,
+            &format!(
+                r#"{{
+                    "mediaType": "application/vnd.oci.image.index.v1+json",
+                    "manifests": [
+                        {{"digest": "{AMD64_DIGEST}", "platform": {{"os": "linux", "architecture": "amd64"}}}},
+                        {{"digest": "{ARM64_DIGEST}", "platform": {{"os": "linux", "architecture": "arm64"}}}}
+                    ],
+                    "annotations": {{"org.opencontainers.image.version": "1.0"}}
+                }}"#
+            ),
+        )?;
+        for digest in [AMD64_DIGEST, ARM64_DIGEST] {
+            write_blob(
+                path,
+                digest,
+                &format!(
+                    r#"{{
+                        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                        "config": {{"digest": "{CONFIG_DIGEST}"}},
+                        "annotations": {{"org.opencontainers.image.revision": "{digest}"}}
+                    }}"#
+                ),
+            )?;
+        }
+        write_blob(path, CONFIG_DIGEST, CONFIG)?;
+
+        Ok(layout)
+    }
+
+    /// OCI layout with the platforms of an untagged image listed directly in its index
+    fn flat_layout() -> Result<TempDir> {
+        let layout = TempDir::new()?;
+        let path = layout.path();
+
+        fs::write(
+            path.join("oci-layout"),
+            r#"{"imageLayoutVersion": "1.0.0"}"#,
+        )?;
+        fs::write(
+            path.join("index.json"),
+            format!(
+                r#"{{
+                    "schemaVersion": 2,
+                    "manifests": [
+                        {{"digest": "{AMD64_DIGEST}", "platform": {{"os": "linux", "architecture": "amd64"}}}},
+                        {{"digest": "{ARM64_DIGEST}", "platform": {{"os": "linux", "architecture": "arm64"}}}}
+                    ]
+                }}"#
+            ),
+        )?;
+
+        for digest in [AMD64_DIGEST, ARM64_DIGEST] {
+            write_blob(
+                path,
+                digest,
+                &format!(
+                    r#"{{
+                        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                        "config": {{"digest": "{CONFIG_DIGEST}"}},
+                        "annotations": {{"org.opencontainers.image.version": "{digest}"}}
+                    }}"#
+                ),
+            )?;
+        }
+        write_blob(path, CONFIG_DIGEST, CONFIG)?;
+
+        Ok(layout)
+    }
+
+    mod public {
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+            assert_eq!(
+                Archive::parse("docker-archive:image.tar"),
+                Some(Archive::Docker {
+                    path: PathBuf::from("image.tar"),
+                    reference: None
+                })
+            );
+            assert_eq!(
+                Archive::parse("docker-archive:image.tar:owner/repo:1.0"),
+                Some(Archive::Docker {
+                    path: PathBuf::from("image.tar"),
+                    reference: Some("owner/repo:1.0".to_string())
+                })
+            );
+            assert_eq!(
+                Archive::parse("oci:./layout:1.0"),
+                Some(Archive::Oci {
+                    path: PathBuf::from("./layout"),
+                    reference: Some("1.0".to_string())
+                })
+            );
+            assert_eq!(Archive::parse("owner/repo:1.0"), None);
+        }
+
+        #[test]
+        fn test_inspect_docker_archive() -> Result<()> {
+            let manifest = r#"[{"Config": "blobs/sha256/1111", "RepoTags": ["owner/repo:1.0"], "Layers": []}]"#;
+            for compress in [false, true] {
+                let (_directory, path) = tarball(
+                    &[("manifest.json", manifest), ("blobs/sha256/1111", CONFIG)],
+                    compress,
+                )?;
+
+                let image = Archive::Docker {
+                    path,
+                    reference: None,
+                }
+                .inspect(&Platform::host())?;
+
+                assert_eq!(image.labels["org.opencontainers.image.revision"], "abc");
+                assert_eq!(image.name.as_deref(), Some("owner/repo:1.0"));
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn test_inspect_docker_archive_missing_reference() -> Result<()> {
+            let (_directory, path) = tarball(
+                &[(
+                    "manifest.json",
+                    r#"[{"Config": "config.json", "RepoTags": ["owner/repo:1.0"]}]"#,
+                )],
+                false,
+            )?;
+
+            assert!(
+                Archive::Docker {
+                    path,
+                    reference: Some("owner/repo:2.0".to_string())
+                }
+                .inspect(&Platform::host())
+                .is_err()
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_inspect_oci_layout() -> Result<()> {
+            let layout = layout()?;
+
+            let image = Archive::Oci {
+                path: layout.path().to_path_buf(),
+                reference: Some("1.0".to_string()),
+            }
+            .inspect(&"linux/arm64".parse()?)?;
+
+            assert_eq!(
+                image.labels["org.opencontainers.image.source"],
+                "https://github.com/owner/repo"
+            );
+            // Config labels take precedence over the manifest annotations
+            assert_eq!(image.labels["org.opencontainers.image.revision"], "abc");
+            assert_eq!(image.labels["org.opencontainers.image.version"], "1.0");
+            assert_eq!(image.name.as_deref(), Some("ghcr.io/owner/repo:1.0"));
+            Ok(())
+        }
+
+        #[test]
+        fn test_inspect_oci_layout_missing_tag() -> Result<()> {
+            let layout = layout()?;
+
+            assert!(
+                Archive::Oci {
+                    path: layout.path().to_path_buf(),
+                    reference: Some("2.0".to_string()),
+                }
+                .inspect(&Platform::host())
+                .is_err()
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_inspect_oci_layout_platforms() -> Result<()> {
+            let layout = flat_layout()?;
+            let image = |platform: &str| -> Result<ArchiveImage> {
+                Archive::Oci {
+                    path: layout.path().to_path_buf(),
+                    reference: None,
+                }
+                .inspect(&platform.parse()?)
+            };
+
+            assert_eq!(
+                image("linux/arm64")?.labels["org.opencontainers.image.version"],
+                ARM64_DIGEST
+            );
+            assert_eq!(
+                image("linux/amd64")?.labels["org.opencontainers.image.version"],
+                AMD64_DIGEST
+            );
+            assert!(image("linux/s390x").is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn test_inspect_not_oci_layout() -> Result<()> {
+            let directory = TempDir::new()?;
+
+            assert!(
+                Archive::Oci {
+                    path: directory.path().to_path_buf(),
+                    reference: None,
+                }
+                .inspect(&Platform::host())
+                .is_err()
+            );
+            Ok(())
+        }
+    }
+
+    mod private {
+        use super::*;
+
+        #[test]
+        fn test_blob_path() {
+            assert_eq!(
+                Archive::blob_path(Path::new("layout"), "sha256", "abc"),
+                PathBuf::from("layout/blobs/sha256/abc")
+            );
+        }
+    }
+}
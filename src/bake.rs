@@ -0,0 +1,483 @@
+use crate::{
+    discovery::Discovery,
+    parser::{ImageReference, Parser},
+    variables::{BAKE, expand},
+};
+use anyhow::{Result, bail};
+use hcl::{
+    Body,
+    eval::{Context, Evaluate},
+};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// File names `docker buildx bake` reads by default
+pub const BAKE_FILES: [&str; 4] = [
+    "docker-bake.hcl",
+    "docker-bake.json",
+    "docker-bake.override.hcl",
+    "docker-bake.override.json",
+];
+
+/// Group built when bake is run without targets
+const DEFAULT_GROUP: &str = "default";
+
+/// Targets and groups of a bake file
+#[derive(Debug, Default, PartialEq)]
+pub struct Bake {
+    pub targets: BTreeMap<String, Target>,
+    pub groups: HashMap<String, Vec<String>>,
+}
+
+/// Attributes of a target before inheritance, unset attributes are taken from inherited targets
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct Target {
+    pub context: Option<String>,
+    pub dockerfile: Option<String>,
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub inherits: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct BakeJson {
+    #[serde(default)]
+    variable: HashMap<String, VariableJson>,
+    #[serde(default)]
+    group: HashMap<String, GroupJson>,
+    #[serde(default)]
+    target: BTreeMap<String, Target>,
+}
+
+#[derive(Deserialize)]
+struct VariableJson {
+    default: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct GroupJson {
+    #[serde(default)]
+    targets: Vec<String>,
+}
+
+impl Target {
+    /// Path of the Dockerfile relative to the bake file, `dockerfile` is relative to the context
+    pub fn dockerfile(&self) -> String {
+        Discovery::join(
+            self.context.as_deref().unwrap_or("."),
+            self.dockerfile.as_deref().unwrap_or("Dockerfile"),
+        )
+    }
+
+    /// Check if any of the tags is the repository of the reference
+    pub fn pushes(&self, image: &ImageReference) -> bool {
+        self.tags
+            .iter()
+            .flatten()
+            .any(|tag| Parser::parse_image(tag).is_ok_and(|tag| tag.same_repository(image)))
+    }
+
+    /// Fill the unset attributes from an inherited target
+    fn inherit(&mut self, parent: &Target) {
+        self.context = self.context.take().or_else(|| parent.context.clone());
+        self.dockerfile = self.dockerfile.take().or_else(|| parent.dockerfile.clone());
+        self.tags = self.tags.take().or_else(|| parent.tags.clone());
+    }
+}
+
+impl Bake {
+    /// Check if a path is a bake file
+    pub fn bake_file(path: &str) -> bool {
+        let file = path.rsplit('/').next().unwrap_or(path);
+        BAKE_FILES.contains(&file)
+    }
+
+    /// Parse a bake file in either format based on its path
+    pub fn parse(path: &str, input: &str) -> Result<Self> {
+        if path.ends_with(".json") {
+            Self::parse_json(input)
+        } else {
+            Self::parse_hcl(input)
+        }
+    }
+
+    /// Parse an HCL bake file
+    ///
+    /// Variables are evaluated from their defaults. Attributes which cannot be evaluated
+    /// statically (e.g. function calls) are left unset.
+    pub fn parse_hcl(input: &str) -> Result<Self> {
+        let body: Body = hcl::parse(input)?;
+        let mut context = Context::new();
+
+        for block in body
+            .blocks()
+            .filter(|block| block.identifier() == "variable")
+        {
+            let [name] = block.labels() else {
+                bail!("Variable block must have exactly one label");
+            };
+
+            let value = block
+                .body()
+                .attributes()
+                .find(|attribute| attribute.key() == "default")
+                .and_then(|attribute| attribute.expr().evaluate(&context).ok())
+                .unwrap_or(hcl::Value::String(String::new()));
+
+            context.declare_var(name.as_str().to_string(), value);
+        }
+
+        let mut bake = Self::default();
+
+        for block in body.blocks() {
+            let [name] = block.labels() else {
+                continue;
+            };
+            let name = name.as_str().to_string();
+
+            let evaluate = |key: &str| {
+                block
+                    .body()
+                    .attributes()
+                    .find(|attribute| attribute.key() == key)
+                    .and_then(|attribute| attribute.expr().evaluate(&context).ok())
+            };
+
+            match block.identifier() {
+                "group" => {
+                    let targets = evaluate("targets")
+                        .map(|value| Self::strings(&value))
+                        .unwrap_or_default();
+                    bake.groups.entry(name).or_default().extend(targets);
+                }
+                "target" => {
+                    let target = Target {
+                        context: evaluate("context").as_ref().and_then(Self::string),
+                        dockerfile: evaluate("dockerfile").as_ref().and_then(Self::string),
+                        tags: evaluate("tags").map(|value| Self::strings(&value)),
+                        inherits: evaluate("inherits")
+                            .map(|value| Self::strings(&value))
+                            .unwrap_or_default(),
+                    };
+
+                    bake.insert(name, target);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(bake)
+    }
+
+    /// Parse a JSON bake file, `${VAR}` in strings is replaced with the variable defaults
+    pub fn parse_json(input: &str) -> Result<Self> {
+        let json: BakeJson = serde_json::from_str(input)?;
+
+        let variables: HashMap<String, String> = json
+            .variable
+            .into_iter()
+            .map(|(name, variable)| {
+                let value = match variable.default {
+                    Some(serde_json::Value::String(value)) => value,
+                    Some(value) => value.to_string(),
+                    None => String::new(),
+                };
+                (name, value)
+            })
+            .collect();
+
+        let interpolate = |value: String| expand(&value, &variables, BAKE);
+
+        let targets = json
+            .target
+            .into_iter()
+            .map(|(name, target)| {
+                let target = Target {
+                    context: target.context.map(interpolate).transpose()?,
+                    dockerfile: target.dockerfile.map(interpolate).transpose()?,
+                    tags: target
+                        .tags
+                        .map(|tags| tags.into_iter().map(interpolate).collect())
+                        .transpose()?,
+                    inherits: target.inherits,
+                };
+                Ok((name, target))
+            })
+            .collect::<Result<_>>()?;
+
+        let groups = json
+            .group
+            .into_iter()
+            .map(|(name, group)| (name, group.targets))
+            .collect();
+
+        Ok(Self { targets, groups })
+    }
+
+    /// Merge a later bake file (e.g. `docker-bake.override.hcl`) over this one like bake does
+    pub fn merge(&mut self, other: Bake) {
+        for (name, target) in other.targets {
+            self.insert(name, target);
+        }
+        for (name, targets) in other.groups {
+            self.groups.entry(name).or_default().extend(targets);
+        }
+    }
+
+    /// Resolve a target with its inherited attributes
+    pub fn target(&self, name: &str) -> Option<Target> {
+        self.resolve(name, &mut HashSet::new())
+    }
+
+    /// Targets built by a group, nested groups are expanded
+    pub fn group_targets(&self, group: &str) -> Vec<String> {
+        let mut targets = vec![];
+        self.expand_group(group, &mut targets, &mut HashSet::new());
+        targets
+    }
+
+    /// Find the Dockerfile of the target tagged with the image
+    ///
+    /// Targets built by the default group are preferred when several are tagged with the image.
+    pub fn dockerfile(&self, image: &ImageReference) -> Option<String> {
+        let default = self.group_targets(DEFAULT_GROUP);
+
+        let mut matches: Vec<(&String, Target)> = self
+            .targets
+            .keys()
+            .filter_map(|name| Some((name, self.target(name)?)))
+            .filter(|(_, target)| target.pushes(image))
+            .collect();
+
+        matches.sort_by_key(|(name, _)| !default.contains(name));
+
+        matches.first().map(|(_, target)| target.dockerfile())
+    }
+
+    /// Add a target, repeated targets are merged with the later one taking precedence
+    fn insert(&mut self, name: String, target: Target) {
+        match self.targets.get_mut(&name) {
+            Some(existing) => {
+                let mut merged = target;
+                merged.inherit(existing);
+                merged.inherits.append(&mut existing.inherits);
+                *existing = merged;
+            }
+            None => {
+                self.targets.insert(name, target);
+            }
+        }
+    }
+
+    fn resolve(&self, name: &str, seen: &mut HashSet<String>) -> Option<Target> {
+        if !seen.insert(name.to_string()) {
+            return None;
+        }
+
+        let mut target = self.targets.get(name)?.clone();
+
+        // Later parents take precedence over earlier ones
+        for parent in target.inherits.clone().iter().rev() {
+            if let Some(parent) = self.resolve(parent, seen) {
+                target.inherit(&parent);
+            }
+        }
+
+        seen.remove(name);
+        Some(target)
+    }
+
+    fn expand_group(&self, group: &str, targets: &mut Vec<String>, seen: &mut HashSet<String>) {
+        if !seen.insert(group.to_string()) {
+            return;
+        }
+
+        for name in self.groups.get(group).into_iter().flatten() {
+            if self.groups.contains_key(name) {
+                self.expand_group(name, targets, seen);
+            } else if !targets.contains(name) {
+                targets.push(name.clone());
+            }
+        }
+    }
+
+    fn string(value: &hcl::Value) -> Option<String> {
+        match value {
+            hcl::Value::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn strings(value: &hcl::Value) -> Vec<String> {
+        match value {
+            hcl::Value::Array(values) => values.iter().filter_map(Self::string).collect(),
+            value => Self::string(value).into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HCL: &str = r#"
+variable "REGISTRY" {
+  default = "ghcr.io/owner"
+}
+
+variable "TAG" {
+  default = "latest"
+}
+
+group "default" {
+  targets = ["app"]
+}
+
+group "all" {
+  targets = ["default", "worker"]
+}
+
+target "_common" {
+  context = "."
+  dockerfile = "docker/Dockerfile"
+}
+
+target "app" {
+  inherits = ["_common"]
+  tags = ["${REGISTRY}/app:${TAG}", "owner/app:${TAG}"]
+}
+
+target "app-debug" {
+  inherits = ["app"]
+  dockerfile = "docker/Dockerfile.debug"
+}
+
+target "worker" {
+  context = "services/worker"
+  tags = ["${REGISTRY}/worker:${TAG}", formatdate("YYYY", timestamp())]
+}
+"#;
+
+    const JSON: &str = r#"{
+  "variable": { "REGISTRY": { "default": "ghcr.io/owner" } },
+  "group": { "default": { "targets": ["app"] } },
+  "target": {
+    "_common": { "context": "." },
+    "app": {
+      "inherits": ["_common"],
+      "dockerfile": "app.Dockerfile",
+      "tags": ["${REGISTRY}/app:latest"]
+    }
+  }
+}"#;
+
+    mod public {
+        use super::*;
+
+        #[test]
+        fn test_bake_file() {
+            assert!(Bake::bake_file("docker-bake.hcl"));
+            assert!(Bake::bake_file("deploy/docker-bake.json"));
+            assert!(!Bake::bake_file("docker-compose.yml"));
+        }
+
+        #[test]
+        fn test_parse_hcl() -> Result<()> {
+            let bake = Bake::parse_hcl(HCL)?;
+
+            assert_eq!(
+                bake.target("app"),
+                Some(Target {
+                    context: Some(".".to_string()),
+                    dockerfile: Some("docker/Dockerfile".to_string()),
+                    tags: Some(vec![
+                        "ghcr.io/owner/app:latest".to_string(),
+                        "owner/app:latest".to_string()
+                    ]),
+                    inherits: vec!["_common".to_string()],
+                })
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_parse_hcl_unevaluated_attribute() -> Result<()> {
+            let bake = Bake::parse_hcl(HCL)?;
+
+            assert_eq!(bake.target("worker").and_then(|target| target.tags), None);
+            Ok(())
+        }
+
+        #[test]
+        fn test_parse_json() -> Result<()> {
+            let bake = Bake::parse_json(JSON)?;
+
+            assert_eq!(
+                bake.dockerfile(&Parser::parse_image("ghcr.io/owner/app:1.0")?),
+                Some("app.Dockerfile".to_string())
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_group_targets() -> Result<()> {
+            let bake = Bake::parse_hcl(HCL)?;
+
+            assert_eq!(bake.group_targets("all"), vec!["app", "worker"]);
+            Ok(())
+        }
+
+        #[test]
+        fn test_dockerfile_prefers_default_group() -> Result<()> {
+            let bake = Bake::parse_hcl(HCL)?;
+
+            // app-debug inherits the tags of app but is not in the default group
+            assert_eq!(
+                bake.dockerfile(&Parser::parse_image("owner/app")?),
+                Some("docker/Dockerfile".to_string())
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_dockerfile_no_match() -> Result<()> {
+            let bake = Bake::parse_hcl(HCL)?;
+
+            assert_eq!(bake.dockerfile(&Parser::parse_image("owner/other")?), None);
+            Ok(())
+        }
+    }
+
+    mod private {
+        use super::*;
+
+        #[test]
+        fn test_resolve_cycle() {
+            let bake = Bake {
+                targets: BTreeMap::from([
+                    (
+                        "a".to_string(),
+                        Target {
+                            inherits: vec!["b".to_string()],
+                            ..Default::default()
+                        },
+                    ),
+                    (
+                        "b".to_string(),
+                        Target {
+                            context: Some("b".to_string()),
+                            inherits: vec!["a".to_string()],
+                            ..Default::default()
+                        },
+                    ),
+                ]),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                bake.target("a").and_then(|target| target.context),
+                Some("b".to_string())
+            );
+        }
+    }
+}
@@ -1,139 +1,1160 @@
 use crate::{
+    archive::Archive,
+    compose::Compose,
+    daemon::Engines,
+    discovery::Discovery,
     docker::{DEFAULT_REVISION, DEFAULT_SOURCE, Docker, OLD_REVISION, OLD_SOURCE},
-    github::Github,
-    parser::Parser,
+    dockerfile::{Dockerfile, SCRATCH},
+    github::{self, Github},
+    gitlab::{self, Gitlab},
+    highlight,
+    kubernetes::Kubernetes,
+    library::Library,
+    lineage::{Lineage, Stop},
+    parser::{DEFAULT_TAG, ImageReference, Parser},
+    platform::Platform,
+    registry::{self, PlatformMismatch, Registry},
+    resolution::{
+        Candidate, Failure, Resolution, Revision, RevisionKind, SCHEMA_VERSION, Strategy,
+    },
+    trace::Trace,
+    workflow::{Build, Workflow},
 };
 use anyhow::{Context, Result, bail};
-use std::process::Command;
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Paths checked for a Dockerfile relative to the root of a repository
+const DOCKERFILE_PATHS: [&str; 2] = ["Dockerfile", "docker/Dockerfile"];
+
+/// Maximum number of Dockerfiles ranked by their path kept as candidates
+const MAX_RANKED: usize = 10;
 
 #[derive(clap::Parser)]
+// Files are resolved instead of an image, one kind at a time and printed as a table
+#[clap(group(
+    clap::ArgGroup::new("files")
+        .args(["dockerfile", "compose", "k8s", "workflow"])
+        .conflicts_with_all(["image", "all", "cat", "save", "lineage"])
+))]
 pub struct Cli {
-    /// Docker image name with optional tag (e.g., project:reference)
-    pub image: String,
+    /// Docker image name with optional tag (e.g., project:reference), docker-archive:file.tar or oci:directory[:tag]
+    #[clap(required_unless_present = "files")]
+    pub image: Option<String>,
 
-    /// GitHub username
-    #[clap(env = "GITHUB_USER", hide_env_values = true)]
-    pub user: String,
+    /// GitHub username, only needed for images in GHCR which refuse anonymous pulls
+    #[clap(env = "GITHUB_USER", hide_env_values = true, requires = "token")]
+    pub user: Option<String>,
 
     /// GitHub token with read access to packages
-    #[clap(env = "GITHUB_TOKEN", hide_env_values = true)]
-    pub token: String,
+    #[clap(env = "GITHUB_TOKEN", hide_env_values = true, requires = "user")]
+    pub token: Option<String>,
+
+    /// Platform to select from multi-platform images as os/arch[/variant] [default: host]
+    #[clap(long)]
+    pub platform: Option<Platform>,
+
+    /// Container engine used to inspect local images
+    #[clap(long, value_enum, default_value_t = Engines::Auto)]
+    pub engine: Engines,
+
+    /// Print the URL instead of opening it, the default without a display
+    #[clap(long, visible_alias = "no-open")]
+    pub print: bool,
+
+    /// Format of the result, json prints a document describing the resolution
+    #[clap(long, value_enum, default_value_t = Output::Text)]
+    pub output: Output,
+
+    /// Print every candidate Dockerfile with its confidence instead of opening the best
+    #[clap(long, conflicts_with_all = ["cat", "save", "lineage", "output"])]
+    pub all: bool,
+
+    /// Print the contents of the Dockerfile instead of opening it
+    #[clap(long, conflicts_with_all = ["lineage", "output"])]
+    pub cat: bool,
+
+    /// Save the Dockerfile to a path instead of opening it
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["lineage", "output"])]
+    pub save: Option<PathBuf>,
+
+    /// Print the tree of base images the Dockerfile is built from, resolved recursively
+    #[clap(long)]
+    pub lineage: bool,
+
+    /// Maximum number of base images followed below the image with --lineage
+    #[clap(long, default_value_t = 5)]
+    pub depth: usize,
+
+    /// Resolve every image a local Dockerfile builds from, copies from or mounts instead of an image
+    #[clap(long, value_name = "PATH")]
+    pub dockerfile: Option<PathBuf>,
+
+    /// Override the default of an argument of the Dockerfile, NAME alone takes the environment variable
+    #[clap(long = "build-arg", value_name = "NAME=VALUE", value_parser = build_arg, requires = "dockerfile")]
+    pub build_args: Vec<(String, String)>,
+
+    /// Resolve the image of every service of compose files instead of an image, later files override earlier ones
+    #[clap(short = 'f', long = "compose", value_name = "FILE")]
+    pub compose: Vec<PathBuf>,
+
+    /// Resolve the image of every container of Kubernetes manifests in a file, a directory or - for stdin instead of an image
+    #[clap(long, value_name = "PATH")]
+    pub k8s: Option<PathBuf>,
+
+    /// Resolve every image GitHub Actions workflows run in a file or a directory instead of an image
+    #[clap(long, value_name = "PATH")]
+    pub workflow: Option<PathBuf>,
+
+    /// Print every step attempted to resolve the image and its outcome
+    #[clap(short = 'v', long)]
+    pub explain: bool,
+
+    /// Variables of the shell, without the `.env` file of the current directory
+    #[clap(skip)]
+    pub environment: HashMap<String, String>,
+
+    #[clap(skip)]
+    trace: Trace,
+
+    #[clap(skip)]
+    github: Github,
+
+    /// Platform of the local image being resolved, used unless --platform is given
+    #[clap(skip)]
+    local_platform: Option<Platform>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Output {
+    Text,
+    Json,
 }
 
 impl Cli {
-    pub async fn run(self) -> Result<()> {
-        // Check if there is a local image and inspect its labels to construct a url
-        let source = Docker::inspect(&self.image, DEFAULT_SOURCE)
-            .or_else(|| Docker::inspect(&self.image, OLD_SOURCE));
-        let revision = Docker::inspect(&self.image, DEFAULT_REVISION)
-            .or_else(|| Docker::inspect(&self.image, OLD_REVISION));
-
-        if let (Some(source), Some(revision)) = (source, revision) {
-            if Github::sha(&revision) {
-                let source = Github::format_source(&source);
-                if let Ok((owner, repo)) = Github::split_source(&source) {
-                    if let Ok(true) = Github::new()
-                        .file_exists(&owner, &repo, "Dockerfile", &revision)
-                        .await
-                    {
-                        let url = Github::web_url(&owner, &repo, "Dockerfile", &revision);
-                        println!("Opening {url}");
-                        return open(&url);
-                    }
+    pub async fn run(mut self) -> Result<()> {
+        if let Some(path) = &self.dockerfile {
+            let images = self
+                .dockerfile_images(path)?
+                .into_iter()
+                .map(|image| (image.clone(), image))
+                .collect();
+            return self.resolve_images(None, images).await;
+        }
+
+        if !self.compose.is_empty() {
+            let compose = Compose::load(&self.compose, &self.environment)?;
+            let images = compose
+                .images()
+                .into_iter()
+                .map(|(service, image)| (service.to_string(), image.to_string()))
+                .collect();
+            return self.resolve_images(Some("service"), images).await;
+        }
+
+        if let Some(path) = &self.k8s {
+            let images = Kubernetes::read(path)?
+                .into_iter()
+                .map(|container| (container.name, container.image))
+                .collect();
+            return self.resolve_images(Some("container"), images).await;
+        }
+
+        if let Some(path) = &self.workflow {
+            let images = Workflow::read(path)?
+                .into_iter()
+                .map(|usage| (usage.location, usage.image))
+                .collect();
+            return self.resolve_images(Some("location"), images).await;
+        }
+
+        // Clap requires the image without another source of images
+        let input = self.image.clone().unwrap_or_default();
+
+        if self.lineage {
+            let lineage = self.lineage(&input).await;
+
+            if self.explain {
+                eprint!("{}", self.trace);
+            }
+
+            let lineage = lineage?;
+            match self.output {
+                Output::Json => println!("{}", serde_json::to_string_pretty(&lineage)?),
+                Output::Text => print!("{lineage}"),
+            }
+
+            return Ok(());
+        }
+
+        let resolution = self.resolve(&input).await;
+
+        if self.explain {
+            eprint!("{}", self.trace);
+        }
+
+        if self.output == Output::Json {
+            let document = match &resolution {
+                Ok(resolution) => serde_json::to_string_pretty(resolution)?,
+                Err(error) => serde_json::to_string_pretty(&Failure {
+                    version: SCHEMA_VERSION,
+                    input,
+                    error: format!("{error:#}"),
+                })?,
+            };
+            println!("{document}");
+
+            return resolution.map(|_| ());
+        }
+
+        let resolution = resolution?;
+
+        if self.all {
+            for candidate in &resolution.candidates {
+                println!("{candidate}");
+            }
+            return Ok(());
+        }
+
+        if self.cat || self.save.is_some() {
+            let dockerfile = match choose(&resolution)? {
+                Some(candidate) => candidate.dockerfile.clone(),
+                None => resolution.dockerfile.clone(),
+            };
+            let contents = self.download(&resolution, dockerfile.as_deref()).await?;
+
+            if let Some(path) = &self.save {
+                fs::write(path, &contents).with_context(|| {
+                    format!("Failed to save the Dockerfile to {}", path.display())
+                })?;
+                eprintln!(
+                    "Saved {} to {}",
+                    dockerfile.unwrap_or_default(),
+                    path.display()
+                );
+            }
+
+            if self.cat {
+                if io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none() {
+                    print!("{}", highlight::dockerfile(&contents));
+                } else {
+                    print!("{contents}");
                 }
             }
+            return Ok(());
+        }
+
+        // SSH sessions, containers and CI have no browser to open the URL with
+        if self.print || headless() {
+            println!("{}", resolution.url);
+            return Ok(());
         }
 
-        let (registry, parts, reference) = Parser::parse_image(&self.image);
+        let url = match choose(&resolution)? {
+            Some(candidate) => candidate.url.clone(),
+            None => resolution.url,
+        };
 
-        let url = self.url(registry, parts, reference).await?;
         println!("Opening {url}");
-        open(&url)?;
+        open(&url)
+    }
+
+    /// Resolve the URL of the Dockerfile, or the closest page to it, for the image
+    async fn resolve(&mut self, input: &str) -> Result<Resolution> {
+        let resolution = self.search(input).await?.or_candidate();
+
+        // Archives without an image name have no reference
+        let image = match Archive::parse(input) {
+            Some(_) => None,
+            None => Parser::parse_image(input).ok(),
+        };
+
+        Ok(resolution.with_input(input, image.as_ref()))
+    }
+
+    /// Resolve every image and print a table of the images and their Dockerfile URLs
+    ///
+    /// Images are paired with where they are used (e.g. the compose service), listed as `key`
+    /// when there is one.
+    async fn resolve_images(
+        &mut self,
+        key: Option<&str>,
+        images: Vec<(String, String)>,
+    ) -> Result<()> {
+        // Images used in several places are only resolved once
+        let mut resolutions: HashMap<String, Result<Resolution, String>> = HashMap::new();
+        for (_, image) in &images {
+            if !resolutions.contains_key(image) {
+                let resolution = self.resolve(image).await;
+                resolutions.insert(
+                    image.clone(),
+                    resolution.map_err(|error| format!("{error:#}")),
+                );
+            }
+        }
+
+        if self.explain {
+            eprint!("{}", self.trace);
+        }
+
+        match self.output {
+            Output::Json => {
+                let mut documents = vec![];
+                for (name, image) in &images {
+                    let mut document = match &resolutions[image] {
+                        Ok(resolution) => serde_json::to_value(resolution)?,
+                        Err(error) => serde_json::to_value(Failure {
+                            version: SCHEMA_VERSION,
+                            input: image.clone(),
+                            error: error.clone(),
+                        })?,
+                    };
+
+                    if let Some(key) = key
+                        && let Some(document) = document.as_object_mut()
+                    {
+                        document.insert(key.to_string(), name.clone().into());
+                    }
+                    documents.push(document);
+                }
+                println!("{}", serde_json::to_string_pretty(&documents)?);
+            }
+            Output::Text => {
+                let width = |column: fn(&(String, String)) -> &String| {
+                    images
+                        .iter()
+                        .map(|row| column(row).len())
+                        .max()
+                        .unwrap_or_default()
+                };
+                let (name_width, image_width) =
+                    (width(|(name, _)| name), width(|(_, image)| image));
+
+                for (name, image) in &images {
+                    if key.is_some() {
+                        print!("{name:<name_width$}  ");
+                    }
+
+                    match &resolutions[image] {
+                        Ok(resolution) => println!("{image:<image_width$}  {}", resolution.url),
+                        Err(error) => println!("{image:<image_width$}  error: {error}"),
+                    }
+                }
+            }
+        }
+
+        let failed = resolutions
+            .values()
+            .filter(|resolution| resolution.is_err())
+            .count();
+        if failed > 0 {
+            bail!("{failed} of {} images failed to resolve", resolutions.len());
+        }
 
         Ok(())
     }
 
-    async fn url(
-        &self,
-        registry: Option<&str>,
-        parts: Vec<&str>,
-        reference: Option<&str>,
-    ) -> Result<String> {
+    /// Read the external images a local Dockerfile references
+    fn dockerfile_images(&self, path: &Path) -> Result<Vec<String>> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        let platform = self.platform();
+        let mut args = Dockerfile::platform_args(&platform);
+        args.extend(self.build_args.iter().cloned());
+
+        let dockerfile = Dockerfile::parse(&contents, &args)?;
+        Ok(dockerfile.images().into_iter().map(String::from).collect())
+    }
+
+    /// Resolve the image and the images its Dockerfile is built from
+    async fn lineage(&mut self, image: &str) -> Result<Lineage> {
+        let resolution = self.resolve(image).await?;
+
+        Ok(self.descend(image, &resolution, 0, &mut vec![]).await)
+    }
+
+    /// Resolve the base images of a resolved image recursively
+    ///
+    /// `ancestors` holds the images above this one to stop at cycles.
+    async fn descend(
+        &mut self,
+        image: &str,
+        resolution: &Resolution,
+        depth: usize,
+        ancestors: &mut Vec<String>,
+    ) -> Lineage {
+        let mut lineage = Lineage::new(image, resolution);
+
+        // Pages other than a Dockerfile have no base images to follow
+        if resolution.dockerfile.is_none() {
+            return lineage;
+        }
+
+        let action = format!("Base images of {image}");
+        let bases = match self.bases(resolution).await {
+            Ok(bases) => {
+                self.trace.record(action, bases.join(", "));
+                bases
+            }
+            Err(error) => {
+                self.trace.error(action, &error);
+                lineage.stop = Some(Stop::Error(format!("{error:#}")));
+                return lineage;
+            }
+        };
+
+        ancestors.push(lineage_key(image));
+        for base in bases {
+            let stop = if base == SCRATCH {
+                Some(Stop::Scratch)
+            } else if ancestors.contains(&lineage_key(&base)) {
+                Some(Stop::Cycle)
+            } else if depth >= self.depth {
+                Some(Stop::Depth)
+            } else {
+                None
+            };
+
+            if let Some(stop) = stop {
+                lineage.bases.push(Lineage::stopped(&base, stop));
+                continue;
+            }
+
+            let node = match self.resolve(&base).await {
+                Ok(resolution) => {
+                    Box::pin(self.descend(&base, &resolution, depth + 1, ancestors)).await
+                }
+                Err(error) => Lineage::stopped(&base, Stop::Error(format!("{error:#}"))),
+            };
+            lineage.bases.push(node);
+        }
+        ancestors.pop();
+
+        lineage
+    }
+
+    /// Download a Dockerfile from the repository and revision of the resolution
+    async fn download(&self, resolution: &Resolution, dockerfile: Option<&str>) -> Result<String> {
+        let (Some(source), Some(revision), Some(dockerfile)) =
+            (&resolution.source, &resolution.revision, dockerfile)
+        else {
+            bail!(
+                "No Dockerfile was found for {}, only {}",
+                resolution.input,
+                resolution.url
+            );
+        };
+
+        if let Some(project) = Gitlab::split_source(source) {
+            return Gitlab::new()
+                .raw(project, dockerfile, revision.pinned())
+                .await;
+        }
+
+        let (owner, repo) = Github::split_source(source)?;
+        self.github
+            .raw(&owner, &repo, dockerfile, revision.pinned())
+            .await
+    }
+
+    /// Download and parse the Dockerfile of the resolution for the images it is built from
+    async fn bases(&self, resolution: &Resolution) -> Result<Vec<String>> {
+        let contents = self
+            .download(resolution, resolution.dockerfile.as_deref())
+            .await?;
+        let platform = self.platform();
+        let dockerfile = Dockerfile::parse(&contents, &Dockerfile::platform_args(&platform))?;
+
+        Ok(dockerfile.bases().into_iter().map(String::from).collect())
+    }
+
+    async fn search(&mut self, input: &str) -> Result<Resolution> {
+        let mut input = input.to_string();
+
+        // Read the labels of images saved on disk without a daemon
+        if let Some(archive) = Archive::parse(&input) {
+            let platform = self.platform();
+            let saved = archive.inspect(&platform)?;
+            self.trace.record(
+                format!("Saved image {input}"),
+                format!("{} labels", saved.labels.len()),
+            );
+
+            if let Some(resolution) = self
+                .labels_url(&saved.labels, Strategy::ArchiveLabels)
+                .await
+            {
+                return Ok(resolution);
+            }
+
+            // Continue with the name the image was saved under
+            let Some(name) = saved.name else {
+                bail!("{input} has no source labels or image name");
+            };
+            input = name;
+        }
+
+        // Check if there is a local image and use its labels to construct a url
+        let mut local = None;
+        for engine in self.engine.engines() {
+            let action = format!("Local image in {engine}");
+            match engine.inspect(&input).await {
+                Ok(Some(inspect)) => {
+                    self.trace.record(action, "found");
+                    local = Some(inspect);
+                    break;
+                }
+                Ok(None) => self.trace.record(action, "not found"),
+                Err(error) => self.trace.error(action, &error),
+            }
+        }
+
+        if let Some(labels) = local.as_ref().and_then(|local| local.labels())
+            && let Some(resolution) = self.labels_url(labels, Strategy::LocalLabels).await
+        {
+            return Ok(resolution);
+        }
+
+        // Registry lookups should select the platform of the local image
+        self.local_platform = local.as_ref().map(|local| local.platform());
+
+        let image = Parser::parse_image(&input)?;
+
+        // Fallback to the labels of the image in its registry, pinned to the local digest if pulled
+        let digest = local.as_ref().and_then(|local| local.digest(&image));
+        if let Some(resolution) = self.remote_labels_url(&image, digest.as_deref()).await? {
+            return Ok(resolution);
+        }
+
+        self.url(&image).await
+    }
+
+    async fn url(&self, image: &ImageReference) -> Result<Resolution> {
         let docker = Docker::new();
-        let github = Github::new();
+        let github = &self.github;
 
-        match (registry, parts.as_slice()) {
+        match (image.registry.as_deref(), image.path().as_slice()) {
             // Case 1: Docker Hub library image (e.g., "project:reference")
             (None, ["library", repo]) => {
-                if !docker.repo_exists("library", repo).await {
+                // Official images are built from the docker-library manifest at a pinned commit
+                let action = format!("Official image {repo}");
+                if image.digest.is_some() && image.tag.is_none() {
+                    // The manifest only lists tags, a digest may belong to any of them
+                    self.trace.record(action, "skipped for a digest");
+                } else {
+                    match Library::new().url(repo, image.tag.as_deref()).await {
+                        Ok(resolution) => {
+                            self.trace.record(action, "listed");
+                            return Ok(resolution);
+                        }
+                        Err(error) => self.trace.error(action, &error),
+                    }
+                }
+
+                if !self.exists(
+                    format!("Docker Hub repo library/{repo}"),
+                    docker.repo_exists("library", repo).await,
+                ) {
                     bail!("Docker Hub repo does not exist");
                 }
 
-                Ok(Docker::web_url(None, repo))
+                Ok(Resolution::new(
+                    Docker::web_url(None, repo),
+                    Strategy::DockerHub,
+                ))
             }
 
             // Case 2: No registry, namespace/repo - GitHub || Docker (e.g., "project/repo:reference")
             (None, [namespace, repo]) => {
-                if let Some(default_branch) = github.check_repo(namespace, repo).await {
-                    // Couple paths to check for Dockerfile
-                    let paths = vec!["Dockerfile", "docker/Dockerfile"];
-
-                    // Found path to Dockerfile
-                    let mut file_path = None;
-
-                    // If reference is provided, use it to get the revision
-                    let revision = if let Some(reference) = reference {
-                        github
-                            .revision(
-                                namespace,
-                                repo,
-                                reference,
-                                &self.user,
-                                &self.token,
-                                &default_branch,
-                            )
-                            .await
-                            .unwrap_or(default_branch)
-                    } else {
-                        default_branch
-                    };
-
-                    for path in paths {
-                        if github
-                            .file_exists(namespace, repo, path, &revision)
-                            .await
-                            .unwrap_or(false)
-                        {
-                            file_path = Some(path.to_string());
-                            break;
-                        }
+                let action = format!("GitHub repo {namespace}/{repo}");
+                let default_branch = match github.check_repo(namespace, repo).await {
+                    Ok(Some(branch)) => {
+                        self.trace
+                            .record(action, format!("default branch {branch}"));
+                        Some(branch)
+                    }
+                    Ok(None) => {
+                        self.trace.record(action, "not found");
+                        None
                     }
+                    Err(error) => {
+                        self.trace.error(action, &error);
+                        None
+                    }
+                };
+
+                if let Some(default_branch) = default_branch {
+                    let revision = self
+                        .github_revision(
+                            &self.registry(github::REGISTRY),
+                            namespace,
+                            repo,
+                            image,
+                            &default_branch,
+                        )
+                        .await?;
+
+                    let candidates = self
+                        .dockerfile(github, namespace, repo, revision.pinned(), image)
+                        .await;
 
-                    if let Some(file_path) = file_path {
-                        return Ok(Github::web_url(namespace, repo, &file_path, &revision));
+                    if let Some(best) = candidates.first() {
+                        let dockerfile = best.dockerfile.clone().unwrap_or_default();
+                        let strategy = best.strategy;
+
+                        return Ok(Resolution::github(
+                            namespace,
+                            repo,
+                            &dockerfile,
+                            revision,
+                            strategy,
+                        )
+                        .with_candidates(candidates));
                     }
                 }
 
-                if !docker.repo_exists(namespace, repo).await {
+                if !self.exists(
+                    format!("Docker Hub repo {namespace}/{repo}"),
+                    docker.repo_exists(namespace, repo).await,
+                ) {
                     bail!("Docker Hub repo does not exist");
                 }
 
-                Ok(Docker::web_url(Some(namespace), repo))
+                Ok(Resolution::new(
+                    Docker::web_url(Some(namespace), repo),
+                    Strategy::DockerHub,
+                ))
+            }
+
+            // Case 3: No registry with a nested path (e.g., "project/repo/subdir")
+            (None, _) => {
+                bail!("Docker Hub repositories only have a namespace and a repo");
             }
 
-            // Case 3: Registry with namespace (e.g., "registry.io/project")
+            // Case 4: Registry with namespace (e.g., "registry.io/project")
             (Some(_registry), [_namespace]) => {
                 bail!("Registry with only a namespace is an invalid image format");
             }
 
-            // Case 4: Registry with namespace and repo (e.g., "registry.io/project/repo:reference")
-            (Some(registry), [namespace, repo]) => {
-                // TODO: if this is ghcr.io then maybe we should open the repo page instead, or try to find the dockerfile in the root and open that
-                Ok(format!("https://{registry}/{namespace}/{repo}"))
+            // Case 5: GitLab registry with a project path (e.g., "registry.gitlab.com/group/subgroup/project/image:reference")
+            (Some(gitlab::REGISTRY), path) => self.gitlab_url(path, image.tag.as_deref()).await,
+
+            // Case 6: Registry with a path of any depth (e.g., "registry.io/project/team/repo:reference")
+            (Some(registry), path) => Ok(Resolution::new(
+                format!("https://{registry}/{}", path.join("/")),
+                Strategy::Registry,
+            )),
+        }
+    }
+
+    /// Find the revision of a GitHub repository an image was built from, pinned to its commit
+    ///
+    /// The revision label of the image in GHCR comes first, then the tag as a git ref and then
+    /// the default branch.
+    async fn github_revision(
+        &self,
+        registry: &Registry,
+        namespace: &str,
+        repo: &str,
+        image: &ImageReference,
+        default_branch: &str,
+    ) -> Result<Revision> {
+        let github = &self.github;
+
+        // The revision label of the image in GHCR is the commit it was built from
+        let label = match image.reference() {
+            Some(reference) => {
+                let action = format!(
+                    "Revision label of {}/{namespace}/{repo} at {reference}",
+                    github::REGISTRY
+                );
+                match github.revision(registry, namespace, repo, reference).await {
+                    Ok(Some(revision)) => {
+                        self.trace.record(action, format!("revision {revision}"));
+                        Some(revision)
+                    }
+                    Ok(None) => {
+                        self.trace.record(action, "missing");
+                        None
+                    }
+                    Err(error) => {
+                        self.trace.error(action, &error);
+                        if self.unavailable(&error) {
+                            return Err(error);
+                        }
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        // Without a label the tag is tried as a git ref before the default branch
+        let mut names = label.into_iter().collect::<Vec<_>>();
+        if names.is_empty() {
+            names.extend(image.tag.clone());
+        }
+        names.push(default_branch.to_string());
+
+        let mut revision = Revision::new(default_branch, RevisionKind::DefaultBranch);
+        for name in names {
+            let kind = if name == default_branch {
+                RevisionKind::DefaultBranch
+            } else if Github::sha(&name) {
+                RevisionKind::Commit
+            } else {
+                RevisionKind::Reference
+            };
+            let mut candidate = Revision::new(&name, kind);
+
+            // Pin tags and branches to their commit so the URL is a permalink
+            if candidate.commit.is_none() {
+                let action = format!("Git ref {name} in {namespace}/{repo}");
+                match github.commit(namespace, repo, &name).await {
+                    Ok(Some((commit, ref_kind))) => {
+                        self.trace.record(action, format!("commit {commit}"));
+                        candidate.commit = Some(commit);
+                        if kind == RevisionKind::Reference {
+                            candidate.kind = ref_kind;
+                        }
+                    }
+                    Ok(None) => {
+                        self.trace.record(action, "not found");
+                        // A tag which is not a git ref falls back to the default branch
+                        if kind == RevisionKind::Reference {
+                            continue;
+                        }
+                    }
+                    Err(error) => self.trace.error(action, &error),
+                }
+            }
+
+            revision = candidate;
+            break;
+        }
+
+        Ok(revision)
+    }
+
+    /// Find the Dockerfiles which may build the image in a GitHub repository at a revision, best first
+    async fn dockerfile(
+        &self,
+        github: &Github,
+        owner: &str,
+        repo: &str,
+        revision: &str,
+        image: &ImageReference,
+    ) -> Vec<Candidate> {
+        // List the whole tree to find Dockerfiles anywhere in the repository
+        let action = format!("Tree of {owner}/{repo} at {revision}");
+        match github.tree(owner, repo, revision).await {
+            Ok(tree) => {
+                let files = format!("{} files", tree.paths.len());
+                if tree.truncated {
+                    self.trace
+                        .record(action, format!("{files}, truncated by GitHub"));
+                } else {
+                    self.trace.record(action, files);
+                }
+
+                let candidates = self
+                    .tree_dockerfile(github, owner, repo, revision, image, &tree.paths)
+                    .await;
+
+                // The Dockerfile may be among the files a truncated tree leaves out
+                if !candidates.is_empty() || !tree.truncated {
+                    return candidates;
+                }
             }
+            Err(error) => self.trace.error(action, &error),
+        }
 
-            _ => unimplemented!("Unsupported image format"),
+        // Probe the conventional paths when the tree is unavailable or the part listed has none
+        for path in DOCKERFILE_PATHS {
+            if self.exists(
+                format!("Dockerfile {path} in {owner}/{repo} at {revision}"),
+                github.file_exists(owner, repo, path, revision).await,
+            ) {
+                let url = Github::web_url(owner, repo, path, revision);
+                return vec![Candidate::new(url, Some(path), Strategy::ConventionalPath)];
+            }
         }
+
+        vec![]
+    }
+
+    /// Rank the Dockerfiles among the files of the repository tree
+    async fn tree_dockerfile(
+        &self,
+        github: &Github,
+        owner: &str,
+        repo: &str,
+        revision: &str,
+        image: &ImageReference,
+        tree: &[String],
+    ) -> Vec<Candidate> {
+        let url = |path: &str| Github::web_url(owner, repo, path, revision);
+        let mut candidates = vec![];
+
+        // Workflows which push the image name the exact Dockerfile
+        let builds = github
+            .workflow_builds(owner, repo, revision, tree, &self.trace)
+            .await;
+        let paths = builds
+            .iter()
+            .filter(|build| build.pushes(image))
+            .map(Build::dockerfile)
+            .filter(|path| tree.contains(path))
+            .collect();
+        candidates.extend(self.hinted(
+            "Workflow builds",
+            Strategy::Workflow,
+            paths,
+            format!("none of {} push the image", builds.len()),
+            url,
+        ));
+
+        // Bake targets tagged with the image name their Dockerfile relative to the bake file,
+        // only downloaded when no workflow named the Dockerfile
+        if candidates.is_empty() {
+            let bakes = github.bakes(owner, repo, revision, tree, &self.trace).await;
+            let paths = bakes
+                .iter()
+                .filter_map(|(directory, bake)| {
+                    Some(Discovery::join(directory, &bake.dockerfile(image)?))
+                })
+                .filter(|path| tree.contains(path))
+                .collect();
+            candidates.extend(self.hinted(
+                "Bake targets",
+                Strategy::Bake,
+                paths,
+                format!("none in {} files tag the image", bakes.len()),
+                url,
+            ));
+        }
+
+        // Compose services with the image name their build relative to the compose file
+        if candidates.is_empty() {
+            let composes = github
+                .composes(owner, repo, revision, tree, &self.trace)
+                .await;
+            let paths = composes
+                .iter()
+                .filter_map(|(directory, compose)| {
+                    Some(Discovery::join(directory, &compose.dockerfile(image)?))
+                })
+                .filter(|path| tree.contains(path))
+                .collect();
+            candidates.extend(self.hinted(
+                "Compose services",
+                Strategy::Compose,
+                paths,
+                format!("none in {} files build the image", composes.len()),
+                url,
+            ));
+        }
+
+        let name = image.path.last().map(String::as_str).unwrap_or(repo);
+        let ranked = Discovery::rank(tree, name);
+        match ranked.first() {
+            Some(best) => self.trace.record(
+                "Dockerfile ranking",
+                format!(
+                    "{} Dockerfiles, {} scored {}",
+                    ranked.len(),
+                    best.path,
+                    best.score
+                ),
+            ),
+            None => self.trace.record("Dockerfile ranking", "no Dockerfiles"),
+        }
+        candidates.extend(ranked.into_iter().take(MAX_RANKED).map(|candidate| {
+            Candidate::ranked(url(&candidate.path), &candidate.path, candidate.score)
+        }));
+
+        Candidate::rank(candidates)
+    }
+
+    /// Record the Dockerfiles which files of a kind (workflows, bake or compose files) name
+    fn hinted(
+        &self,
+        action: &str,
+        strategy: Strategy,
+        paths: Vec<String>,
+        missing: String,
+        url: impl Fn(&str) -> String,
+    ) -> Vec<Candidate> {
+        if paths.is_empty() {
+            self.trace.record(action, missing);
+        } else {
+            self.trace.record(action, paths.join(", "));
+        }
+
+        paths
+            .iter()
+            .map(|path| Candidate::new(url(path), Some(path), strategy))
+            .collect()
+    }
+
+    /// Record the outcome of checking if something exists
+    fn exists(&self, action: String, exists: Result<bool>) -> bool {
+        match &exists {
+            Ok(true) => self.trace.record(action, "found"),
+            Ok(false) => self.trace.record(action, "not found"),
+            Err(error) => self.trace.error(action, error),
+        }
+
+        matches!(exists, Ok(true))
+    }
+
+    /// Platform to resolve, the one given with --platform, of the local image or of the host
+    fn platform(&self) -> Platform {
+        self.platform
+            .clone()
+            .or_else(|| self.local_platform.clone())
+            .unwrap_or_else(Platform::host)
+    }
+
+    /// Registry client for the platform, GitHub credentials are only sent to GHCR
+    fn registry(&self, host: &str) -> Registry {
+        let registry = Registry::new(host).with_platform(self.platform());
+
+        match (host, &self.user, &self.token) {
+            (github::REGISTRY, Some(user), Some(token)) => registry.with_credentials(user, token),
+            _ => registry,
+        }
+    }
+
+    /// Read the source labels from the image config in the remote registry without pulling it
+    ///
+    /// Only an image without the platform asked for with --platform is an error, other failures
+    /// fall back to the next strategy.
+    async fn remote_labels_url(
+        &self,
+        image: &ImageReference,
+        digest: Option<&str>,
+    ) -> Result<Option<Resolution>> {
+        let host = image.registry.as_deref().unwrap_or(registry::DOCKER_HUB);
+        let reference = digest.or(image.reference()).unwrap_or(DEFAULT_TAG);
+
+        let repository = image.path.join("/");
+
+        let action = format!("Registry labels of {host}/{repository} at {reference}");
+        let labels = match self.registry(host).labels(&repository, reference).await {
+            Ok(Some(labels)) => {
+                self.trace
+                    .record(action, format!("{} labels", labels.len()));
+                labels
+            }
+            Ok(None) => {
+                self.trace.record(action, "manifest not found");
+                return Ok(None);
+            }
+            Err(error) => {
+                self.trace.error(action, &error);
+                if self.unavailable(&error) {
+                    return Err(error);
+                }
+                return Ok(None);
+            }
+        };
+
+        Ok(self.labels_url(&labels, Strategy::RegistryLabels).await)
+    }
+
+    /// Check if the error is the image lacking the platform given with --platform
+    fn unavailable(&self, error: &anyhow::Error) -> bool {
+        self.platform.is_some() && error.downcast_ref::<PlatformMismatch>().is_some()
+    }
+
+    /// Build the Dockerfile URL from source and revision labels pinned to a commit on GitHub
+    async fn labels_url(
+        &self,
+        labels: &HashMap<String, String>,
+        strategy: Strategy,
+    ) -> Option<Resolution> {
+        let Some((source, revision)) = source_labels(labels) else {
+            self.trace.record("Source and revision labels", "missing");
+            return None;
+        };
+
+        if !Github::sha(revision) {
+            self.trace
+                .record(format!("Revision label {revision}"), "not a commit SHA");
+            return None;
+        }
+
+        let source = Github::format_source(source);
+        let (owner, repo) = match Github::split_source(&source) {
+            Ok(split) => split,
+            Err(error) => {
+                self.trace.error(format!("Source label {source}"), &error);
+                return None;
+            }
+        };
+
+        if !self.exists(
+            format!("Dockerfile in {owner}/{repo} at {revision}"),
+            self.github
+                .file_exists(&owner, &repo, "Dockerfile", revision)
+                .await,
+        ) {
+            return None;
+        }
+
+        Some(Resolution::github(
+            &owner,
+            &repo,
+            "Dockerfile",
+            Revision::new(revision, RevisionKind::Commit),
+            strategy,
+        ))
+    }
+
+    /// Find the project that owns a GitLab registry path and the Dockerfile within it
+    async fn gitlab_url(&self, path: &[&str], reference: Option<&str>) -> Result<Resolution> {
+        let gitlab = Gitlab::new();
+
+        for project in Gitlab::projects(path) {
+            let action = format!("GitLab project {project}");
+            let default_branch = match gitlab.check_project(&project).await {
+                Ok(Some(branch)) => {
+                    self.trace
+                        .record(action, format!("default branch {branch}"));
+                    branch
+                }
+                Ok(None) => {
+                    self.trace.record(action, "not found");
+                    continue;
+                }
+                Err(error) => {
+                    self.trace.error(action, &error);
+                    continue;
+                }
+            };
+
+            // Components after the project path name the image which is often a subdirectory
+            let image = path[project.split('/').count()..].join("/");
+
+            let mut paths: Vec<String> = DOCKERFILE_PATHS.iter().map(|p| p.to_string()).collect();
+            if !image.is_empty() {
+                paths.insert(0, format!("{image}/Dockerfile"));
+            }
+
+            // Try the reference first and fallback to the default branch
+            let revisions = reference
+                .map(|reference| Revision::new(reference, RevisionKind::Reference))
+                .into_iter()
+                .chain([Revision::new(&default_branch, RevisionKind::DefaultBranch)]);
+
+            for revision in revisions {
+                for file_path in &paths {
+                    if self.exists(
+                        format!("Dockerfile {file_path} in {project} at {}", revision.name),
+                        gitlab
+                            .file_exists(&project, file_path, &revision.name)
+                            .await,
+                    ) {
+                        return Ok(Resolution::gitlab(&project, file_path, revision));
+                    }
+                }
+            }
+
+            return Ok(Resolution {
+                source: Some(Gitlab::web_project_url(&project)),
+                ..Resolution::new(Gitlab::web_project_url(&project), Strategy::GitlabProject)
+            });
+        }
+
+        bail!("GitLab project does not exist");
+    }
+}
+
+/// Find the source and revision labels, preferring the OCI keys over label-schema
+fn source_labels(labels: &HashMap<String, String>) -> Option<(&str, &str)> {
+    let source = labels
+        .get(DEFAULT_SOURCE)
+        .or_else(|| labels.get(OLD_SOURCE))?;
+    let revision = labels
+        .get(DEFAULT_REVISION)
+        .or_else(|| labels.get(OLD_REVISION))?;
+
+    Some((source, revision))
+}
+
+/// Check if there is no X11 or Wayland display to open a browser on
+fn headless() -> bool {
+    ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .all(|variable| env::var_os(variable).is_none_or(|display| display.is_empty()))
+}
+
+/// Parse a build argument as NAME=VALUE, NAME alone takes the value of the environment variable
+fn build_arg(arg: &str) -> Result<(String, String)> {
+    match arg.split_once('=') {
+        Some(("", _)) => bail!("Build argument {arg} has no name"),
+        Some((name, value)) => Ok((name.to_string(), value.to_string())),
+        None => match env::var(arg) {
+            Ok(value) => Ok((arg.to_string(), value)),
+            Err(_) => bail!("Build argument {arg} has no value and no environment variable"),
+        },
+    }
+}
+
+/// Identify an image regardless of how its reference is written (e.g. "node" and "docker.io/library/node:latest")
+fn lineage_key(image: &str) -> String {
+    match Parser::parse_image(image) {
+        Ok(image) => format!(
+            "{}/{}:{}",
+            image.domain(),
+            image.path.join("/"),
+            image.reference().unwrap_or(DEFAULT_TAG)
+        ),
+        Err(_) => image.to_string(),
+    }
+}
+
+/// Ask which candidate to use when the best is a guess and there is a terminal to ask on
+fn choose(resolution: &Resolution) -> Result<Option<&Candidate>> {
+    if resolution.ambiguous() && io::stdin().is_terminal() && io::stdout().is_terminal() {
+        return pick(&resolution.candidates).map(Some);
+    }
+
+    Ok(None)
+}
+
+/// Ask which of the candidates to use, the best is the default
+fn pick(candidates: &[Candidate]) -> Result<&Candidate> {
+    println!("Several Dockerfiles may build the image:");
+    for (index, candidate) in candidates.iter().enumerate() {
+        println!("  {}. {candidate}", index + 1);
+    }
+    print!("Choose [1-{}] (default 1): ", candidates.len());
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(&candidates[choice(&answer, candidates.len())?])
+}
+
+/// Parse the number of the chosen candidate, nothing chooses the first
+fn choice(answer: &str, count: usize) -> Result<usize> {
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Ok(0);
+    }
+
+    match answer.parse::<usize>() {
+        Ok(number) if (1..=count).contains(&number) => Ok(number - 1),
+        _ => bail!("Invalid choice {answer}, expected a number from 1 to {count}"),
     }
 }
 
@@ -155,6 +1176,11 @@ fn open(url: &str) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::trace::Step;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path, query_param},
+    };
 
     const USER: &str = "user";
     const TOKEN: &str = "token";
@@ -163,32 +1189,55 @@ mod tests {
     const NAMESPACE: &str = "project";
     const REPO: &str = "repo";
     const REFERENCE: &str = "reference";
+    const DIGEST: &str = "sha256:aec5512345678901234567890123456789012345678901234567890123456789";
+
+    fn cli(image: String) -> Cli {
+        Cli {
+            image: Some(image),
+            user: Some(USER.to_string()),
+            token: Some(TOKEN.to_string()),
+            dockerfile: None,
+            build_args: vec![],
+            compose: vec![],
+            k8s: None,
+            workflow: None,
+            platform: None,
+            engine: Engines::Auto,
+            print: false,
+            output: Output::Text,
+            all: false,
+            cat: false,
+            save: None,
+            lineage: false,
+            depth: 5,
+            explain: false,
+            environment: HashMap::new(),
+            trace: Trace::default(),
+            github: Github::new(),
+            local_platform: None,
+        }
+    }
 
     // TODO: case 1 & 2 make http calls that are not mocked so skipping tests for now
 
     #[tokio::test]
     #[should_panic]
     async fn test_registry_with_namespace() {
-        let cli = Cli {
-            image: format!("{REGISTRY}/{NAMESPACE}").to_string(),
-            user: USER.to_string(),
-            token: TOKEN.to_string(),
-        };
+        let cli = cli(format!("{REGISTRY}/{NAMESPACE}"));
 
-        cli.url(Some(REGISTRY), vec![NAMESPACE], None)
+        cli.url(&Parser::parse_image(cli.image.as_deref().unwrap()).unwrap())
             .await
             .expect("Unsupported image format");
     }
 
     #[tokio::test]
     async fn test_registry_with_namespace_and_repo() -> Result<()> {
-        let cli = Cli {
-            image: format!("{REGISTRY}/{NAMESPACE}/{REPO}").to_string(),
-            user: USER.to_string(),
-            token: TOKEN.to_string(),
-        };
+        let cli = cli(format!("{REGISTRY}/{NAMESPACE}/{REPO}"));
 
-        let url = cli.url(Some(REGISTRY), vec![NAMESPACE, REPO], None).await?;
+        let url = cli
+            .url(&Parser::parse_image(cli.image.as_deref().unwrap())?)
+            .await?
+            .url;
         assert_eq!(url, format!("https://{REGISTRY}/{NAMESPACE}/{REPO}"));
 
         Ok(())
@@ -197,13 +1246,12 @@ mod tests {
     #[tokio::test]
     async fn test_registry_with_namespace_and_repo_and_reference() -> Result<()> {
         // TODO: reference is unused
-        let cli = Cli {
-            image: format!("{REGISTRY}/{NAMESPACE}/{REPO}:{REFERENCE}").to_string(),
-            user: USER.to_string(),
-            token: TOKEN.to_string(),
-        };
+        let cli = cli(format!("{REGISTRY}/{NAMESPACE}/{REPO}:{REFERENCE}"));
 
-        let url = cli.url(Some(REGISTRY), vec![NAMESPACE, REPO], None).await?;
+        let url = cli
+            .url(&Parser::parse_image(cli.image.as_deref().unwrap())?)
+            .await?
+            .url;
         assert_eq!(url, format!("https://{REGISTRY}/{NAMESPACE}/{REPO}"));
 
         Ok(())
@@ -212,14 +1260,359 @@ mod tests {
     #[tokio::test]
     #[should_panic]
     async fn test_unsupported_image_format() {
+        let cli = cli(format!("{NAMESPACE}/{REPO}/subdir:{REFERENCE}"));
+
+        cli.url(&Parser::parse_image(cli.image.as_deref().unwrap()).unwrap())
+            .await
+            .expect("Unsupported image format");
+    }
+
+    #[tokio::test]
+    async fn test_library_digest() {
+        let image = format!("redis@{DIGEST}");
+        let cli = cli(image.clone());
+
+        // Docker Hub is not mocked, only the skipped docker-library lookup matters
+        let _ = cli.url(&Parser::parse_image(&image).unwrap()).await;
+
+        assert_eq!(
+            cli.trace.steps()[0],
+            Step {
+                action: "Official image redis".to_string(),
+                outcome: "skipped for a digest".to_string(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registry_with_nested_path() -> Result<()> {
+        let cli = cli(format!("{REGISTRY}/{NAMESPACE}/team/{REPO}:{REFERENCE}"));
+
+        let url = cli
+            .url(&Parser::parse_image(cli.image.as_deref().unwrap())?)
+            .await?
+            .url;
+        assert_eq!(url, format!("https://{REGISTRY}/{NAMESPACE}/team/{REPO}"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remote_labels_url() -> Result<()> {
+        let registry = MockServer::start().await;
+        let github = MockServer::start().await;
+        let commit = "0123456789abcdef0123456789abcdef01234567";
+
+        Mock::given(method("GET"))
+            .and(path("/v2/"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&registry)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/v2/{NAMESPACE}/{REPO}/manifests/{REFERENCE}"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                serde_json::json!({ "config": { "digest": DIGEST } }).to_string(),
+                registry::DOCKER_MANIFEST,
+            ))
+            .mount(&registry)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!("/v2/{NAMESPACE}/{REPO}/blobs/{DIGEST}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "config": {
+                    "Labels": {
+                        DEFAULT_SOURCE: "https://github.com/owner/source",
+                        DEFAULT_REVISION: commit
+                    }
+                }
+            })))
+            .mount(&registry)
+            .await;
+
+        Mock::given(method("HEAD"))
+            .and(path("/repos/owner/source/contents/Dockerfile"))
+            .and(query_param("ref", commit))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&github)
+            .await;
+
+        let image = format!("{}/{NAMESPACE}/{REPO}:{REFERENCE}", registry.address());
+        let cli = Cli {
+            github: Github::new().with_api(&github.uri()),
+            ..cli(image.clone())
+        };
+
+        let resolution = cli
+            .remote_labels_url(&Parser::parse_image(&image)?, None)
+            .await?
+            .expect("Labels did not resolve");
+
+        assert_eq!(
+            resolution.url,
+            format!("https://github.com/owner/source/blob/{commit}/Dockerfile")
+        );
+        assert_eq!(resolution.strategy, Strategy::RegistryLabels);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_github_revision_without_label() -> Result<()> {
+        let registry = MockServer::start().await;
+        let github = MockServer::start().await;
+        let commit = "0123456789abcdef0123456789abcdef01234567";
+
+        Mock::given(method("GET"))
+            .and(path("/v2/"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&registry)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!("/v2/{NAMESPACE}/{REPO}/manifests/v1.2.3")))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                serde_json::json!({ "config": { "digest": DIGEST } }).to_string(),
+                registry::DOCKER_MANIFEST,
+            ))
+            .mount(&registry)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!("/v2/{NAMESPACE}/{REPO}/blobs/{DIGEST}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "config": { "Labels": { DEFAULT_SOURCE: "https://github.com/owner/source" } }
+            })))
+            .mount(&registry)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/repos/{NAMESPACE}/{REPO}/git/ref/tags/v1.2.3"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "object": { "sha": commit, "type": "commit" }
+            })))
+            .mount(&github)
+            .await;
+
+        let image = format!("{NAMESPACE}/{REPO}:v1.2.3");
         let cli = Cli {
-            image: format!("{NAMESPACE}/{REPO}/subdir:{REFERENCE}").to_string(),
-            user: USER.to_string(),
-            token: TOKEN.to_string(),
+            github: Github::new().with_api(&github.uri()),
+            ..cli(image.clone())
         };
 
-        cli.url(None, vec![NAMESPACE, REPO, "subdir"], None)
+        let revision = cli
+            .github_revision(
+                &Registry::new(&registry.address().to_string()),
+                NAMESPACE,
+                REPO,
+                &Parser::parse_image(&image)?,
+                "main",
+            )
+            .await?;
+
+        assert_eq!(revision.name, "v1.2.3");
+        assert_eq!(revision.kind, RevisionKind::Tag);
+        assert_eq!(revision.commit.as_deref(), Some(commit));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tree_dockerfile_workflow_first() -> Result<()> {
+        let github = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/{NAMESPACE}/{REPO}/main/.github/workflows/release.yaml"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "jobs:\n  release:\n    steps:\n      - uses: docker/build-push-action@v6\n        with:\n          file: docker/Dockerfile\n          push: true\n          tags: ghcr.io/project/repo:latest\n",
+            ))
+            .mount(&github)
+            .await;
+
+        // Bake files are not downloaded once a workflow named the Dockerfile
+        Mock::given(method("GET"))
+            .and(path(format!("/{NAMESPACE}/{REPO}/main/docker-bake.hcl")))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&github)
+            .await;
+
+        let image = format!("ghcr.io/{NAMESPACE}/{REPO}:1.0");
+        let cli = Cli {
+            github: Github::new().with_api(&github.uri()),
+            ..cli(image.clone())
+        };
+        let tree = [
+            ".github/workflows/release.yaml",
+            "docker-bake.hcl",
+            "docker/Dockerfile",
+            "Dockerfile",
+        ]
+        .map(String::from);
+
+        let candidates = cli
+            .tree_dockerfile(
+                &cli.github,
+                NAMESPACE,
+                REPO,
+                "main",
+                &Parser::parse_image(&image)?,
+                &tree,
+            )
+            .await;
+
+        assert_eq!(
+            candidates[0].dockerfile.as_deref(),
+            Some("docker/Dockerfile")
+        );
+        assert_eq!(candidates[0].strategy, Strategy::Workflow);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remote_labels_url_platform_mismatch() -> Result<()> {
+        let registry = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&registry)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/v2/{NAMESPACE}/{REPO}/manifests/{REFERENCE}"
+            )))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(
+                    serde_json::json!({
+                        "manifests": [{
+                            "digest": DIGEST,
+                            "platform": { "os": "linux", "architecture": "amd64" }
+                        }]
+                    })
+                    .to_string(),
+                    registry::OCI_INDEX,
+                ),
+            )
+            .mount(&registry)
+            .await;
+
+        let image = format!("{}/{NAMESPACE}/{REPO}:{REFERENCE}", registry.address());
+        let image = Parser::parse_image(&image)?;
+
+        // A platform not given with --platform falls back to the next strategy
+        let local = Cli {
+            local_platform: Some("linux/s390x".parse()?),
+            ..cli(image.to_string())
+        };
+        assert!(local.remote_labels_url(&image, None).await?.is_none());
+
+        let requested = Cli {
+            platform: Some("linux/s390x".parse()?),
+            ..cli(image.to_string())
+        };
+        let error = requested
+            .remote_labels_url(&image, None)
             .await
-            .expect("Unsupported image format");
+            .expect_err("Resolved an image without the platform");
+        assert!(error.downcast_ref::<PlatformMismatch>().is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_images_failure() {
+        let image = "docker-archive:/nonexistent/image.tar".to_string();
+        let mut cli = cli(image.clone());
+
+        let error = cli
+            .resolve_images(Some("service"), vec![("web".to_string(), image)])
+            .await
+            .expect_err("Failed images resolved");
+        assert_eq!(error.to_string(), "1 of 1 images failed to resolve");
+    }
+
+    #[test]
+    fn test_choice() -> Result<()> {
+        assert_eq!(choice("\n", 3)?, 0);
+        assert_eq!(choice(" 2\n", 3)?, 1);
+        assert!(choice("4\n", 3).is_err());
+        assert!(choice("0\n", 3).is_err());
+        assert!(choice("first\n", 3).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lineage_key() {
+        assert_eq!(lineage_key("node"), "docker.io/library/node:latest");
+        assert_eq!(
+            lineage_key("docker.io/library/node:latest"),
+            lineage_key("node")
+        );
+        assert_ne!(lineage_key("node:20"), lineage_key("node:22"));
+    }
+
+    #[test]
+    fn test_build_arg() -> Result<()> {
+        assert_eq!(
+            build_arg("VERSION=1.0")?,
+            ("VERSION".to_string(), "1.0".to_string())
+        );
+        assert_eq!(build_arg("EMPTY=")?, ("EMPTY".to_string(), String::new()));
+        assert!(build_arg("=1.0").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_command() {
+        <Cli as clap::CommandFactory>::command().debug_assert();
+    }
+
+    #[test]
+    fn test_conflicting_modes() {
+        let parse = |args: &[&str]| {
+            <Cli as clap::Parser>::try_parse_from(["dis", "redis", USER, TOKEN].iter().chain(args))
+        };
+
+        assert!(parse(&["--output", "json", "--cat"]).is_err());
+        assert!(parse(&["--all", "--save", "Dockerfile"]).is_err());
+        assert!(parse(&["--lineage", "--all"]).is_err());
+        assert!(parse(&["--cat", "--save", "Dockerfile"]).is_ok());
+        assert!(parse(&["--lineage", "--output", "json"]).is_ok());
+    }
+
+    #[test]
+    fn test_conflicting_files() {
+        let parse =
+            |args: &[&str]| <Cli as clap::Parser>::try_parse_from(["dis"].iter().chain(args));
+
+        assert!(parse(&["--dockerfile", "Dockerfile", "-f", "compose.yaml"]).is_err());
+        assert!(parse(&["--dockerfile", "Dockerfile", "redis"]).is_err());
+        assert!(parse(&["--k8s", "manifests", "--all"]).is_err());
+        assert!(parse(&["--workflow", ".github/workflows", "--lineage"]).is_err());
+        assert!(parse(&["--dockerfile", "Dockerfile", "--output", "json"]).is_ok());
+        assert!(parse(&[]).is_err());
+    }
+
+    #[test]
+    fn test_credentials() -> Result<()> {
+        let cli = <Cli as clap::Parser>::try_parse_from(["dis", "redis", USER, TOKEN])?;
+        assert_eq!(cli.user.as_deref(), Some(USER));
+        assert_eq!(cli.token.as_deref(), Some(TOKEN));
+
+        // Images which need no GitHub access resolve without credentials
+        assert!(<Cli as clap::Parser>::try_parse_from(["dis", "redis"]).is_ok());
+        Ok(())
     }
 }
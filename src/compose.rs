@@ -0,0 +1,455 @@
+use crate::{
+    discovery::Discovery,
+    parser::{ImageReference, Parser},
+    variables,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+/// File names `docker compose` reads by default, in order of preference
+pub const COMPOSE_FILES: [&str; 4] = [
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+/// Directories besides the root which commonly hold the compose files of a repository
+const COMPOSE_DIRECTORIES: [&str; 4] = ["docker", ".docker", "deploy", "build"];
+
+/// Services of a compose file
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Compose {
+    #[serde(default)]
+    pub services: BTreeMap<String, Service>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Service {
+    pub image: Option<String>,
+    pub build: Option<Build>,
+}
+
+/// The `build` section of a service, either the context path or the full configuration
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Build {
+    Context(String),
+    Config {
+        context: Option<String>,
+        dockerfile: Option<String>,
+    },
+}
+
+impl Build {
+    /// Path of the Dockerfile relative to the compose file, `dockerfile` is relative to the context
+    pub fn dockerfile(&self) -> String {
+        let (context, dockerfile) = match self {
+            Build::Context(context) => (Some(context.as_str()), None),
+            Build::Config {
+                context,
+                dockerfile,
+            } => (context.as_deref(), dockerfile.as_deref()),
+        };
+
+        Discovery::join(context.unwrap_or("."), dockerfile.unwrap_or("Dockerfile"))
+    }
+}
+
+impl Compose {
+    /// Check if a path is a compose file at the root or in a common subdirectory
+    pub fn compose_file(path: &str) -> bool {
+        let (directory, file) = path.rsplit_once('/').unwrap_or(("", path));
+
+        COMPOSE_FILES.contains(&file)
+            && (directory.is_empty() || COMPOSE_DIRECTORIES.contains(&directory))
+    }
+
+    pub fn parse(input: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    /// Read and merge compose files like `docker compose -f`, later files override earlier ones
+    ///
+    /// Variables are interpolated from the `environment` of the shell and the `.env` file of the
+    /// project (the directory of the first file) and services are merged over the services they
+    /// extend. The file a service extends is relative to the file declaring the service.
+    pub fn load<P: AsRef<Path>>(
+        paths: &[P],
+        environment: &HashMap<String, String>,
+    ) -> Result<Self> {
+        let Some(first) = paths.first() else {
+            bail!("No compose files");
+        };
+        let first = first.as_ref();
+        let environment = Self::environment(first.parent().unwrap_or(Path::new(".")), environment)?;
+
+        let mut merged = Value::Null;
+        for path in paths {
+            let path = path.as_ref();
+            let mut compose = Self::read(path, &environment)?;
+            Self::anchor_extends(&mut compose, path.parent().unwrap_or(Path::new(".")));
+            merge(&mut merged, compose);
+        }
+
+        let empty = Mapping::new();
+        let services = merged
+            .get("services")
+            .and_then(Value::as_mapping)
+            .unwrap_or(&empty);
+
+        let mut compose = Self::default();
+        for name in services.keys().filter_map(Value::as_str) {
+            let service = Self::extend(name, services, first, &environment, &mut vec![])?;
+            let service = serde_yaml::from_value(service)
+                .with_context(|| format!("Invalid service {name}"))?;
+            compose.services.insert(name.to_string(), service);
+        }
+
+        Ok(compose)
+    }
+
+    /// Images of the services by name, services which are only built have none
+    pub fn images(&self) -> Vec<(&str, &str)> {
+        self.services
+            .iter()
+            .filter_map(|(name, service)| Some((name.as_str(), service.image.as_deref()?)))
+            .collect()
+    }
+
+    /// Variables of the `.env` file in the directory, overridden by the environment of the shell
+    fn environment(
+        directory: &Path,
+        shell: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>> {
+        let mut environment = HashMap::new();
+
+        let path = directory.join(".env");
+        if path.is_file() {
+            for variable in dotenvy::from_path_iter(&path)? {
+                let (name, value) = variable?;
+                environment.insert(name, value);
+            }
+        }
+        environment.extend(shell.clone());
+
+        Ok(environment)
+    }
+
+    /// Read a compose file with its variables interpolated
+    fn read(path: &Path, environment: &HashMap<String, String>) -> Result<Value> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut value: Value = serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        interpolate_value(&mut value, environment)
+            .with_context(|| format!("Failed to interpolate {}", path.display()))?;
+        Ok(value)
+    }
+
+    /// Resolve the files services extend against the directory of their own compose file, so
+    /// they stay correct once merged with the services of other files
+    fn anchor_extends(compose: &mut Value, directory: &Path) {
+        let Some(services) = compose.get_mut("services").and_then(Value::as_mapping_mut) else {
+            return;
+        };
+
+        for service in services.values_mut() {
+            if let Some(file) = service
+                .get_mut("extends")
+                .and_then(|extends| extends.get_mut("file"))
+                && let Some(relative) = file.as_str()
+                && let Some(anchored) = directory.join(relative).to_str()
+            {
+                *file = Value::from(anchored);
+            }
+        }
+    }
+
+    /// Merge a service of the file at `path` over the service it extends, recursively
+    ///
+    /// `chain` holds the services being extended to stop at cycles.
+    fn extend(
+        name: &str,
+        services: &Mapping,
+        path: &Path,
+        environment: &HashMap<String, String>,
+        chain: &mut Vec<String>,
+    ) -> Result<Value> {
+        let link = format!("{}#{name}", path.display());
+        if chain.contains(&link) {
+            bail!("Service {name} in {} extends itself", path.display());
+        }
+
+        let Some(service) = services.get(name) else {
+            bail!("Service {name} is not defined in {}", path.display());
+        };
+
+        let (base, file) = match service.get("extends") {
+            None => return Ok(service.clone()),
+            Some(Value::String(base)) => (base.as_str(), None),
+            Some(extends) => (
+                extends
+                    .get("service")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| anyhow!("Service {name} extends no service"))?,
+                extends.get("file").and_then(Value::as_str),
+            ),
+        };
+
+        chain.push(link);
+        let mut extended = match file {
+            None => Self::extend(base, services, path, environment, chain)?,
+            Some(file) => {
+                let directory = path.parent().unwrap_or(Path::new("."));
+                let other = directory.join(file);
+                let compose = Self::read(&other, environment)?;
+                let services = compose
+                    .get("services")
+                    .and_then(Value::as_mapping)
+                    .ok_or_else(|| anyhow!("{} has no services", other.display()))?;
+
+                Self::extend(base, services, &other, environment, chain)?
+            }
+        };
+        chain.pop();
+
+        merge(&mut extended, service.clone());
+        if let Some(extended) = extended.as_mapping_mut() {
+            extended.remove("extends");
+        }
+
+        Ok(extended)
+    }
+
+    /// Find the Dockerfile of the service which builds the image
+    pub fn dockerfile(&self, image: &ImageReference) -> Option<String> {
+        self.services
+            .values()
+            .filter(|service| {
+                service.image.as_deref().is_some_and(|name| {
+                    Parser::parse_image(name).is_ok_and(|name| name.same_repository(image))
+                })
+            })
+            .find_map(|service| service.build.as_ref())
+            .map(Build::dockerfile)
+    }
+}
+
+/// Merge mappings key by key, any other value replaces the previous one
+fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Mapping(base), Value::Mapping(value)) => {
+            for (key, value) in value {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
+/// Interpolate every string of a document
+fn interpolate_value(value: &mut Value, environment: &HashMap<String, String>) -> Result<()> {
+    match value {
+        Value::String(string) => {
+            *string = variables::expand(string, environment, variables::COMPOSE)?
+        }
+        Value::Sequence(sequence) => {
+            for value in sequence {
+                interpolate_value(value, environment)?;
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (_, value) in mapping.iter_mut() {
+                interpolate_value(value, environment)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPOSE: &str = r#"
+services:
+  api:
+    image: ghcr.io/owner/api:latest
+    build:
+      context: ./services/api
+      dockerfile: ../../docker/api.Dockerfile
+  worker:
+    image: owner/worker
+    build: ./worker
+  db:
+    image: postgres:16
+"#;
+
+    #[test]
+    fn test_compose_file() {
+        assert!(Compose::compose_file("compose.yaml"));
+        assert!(Compose::compose_file("docker/docker-compose.yml"));
+        assert!(!Compose::compose_file("examples/compose.yaml"));
+        assert!(!Compose::compose_file("compose.override.yaml"));
+    }
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let compose = Compose::parse(COMPOSE)?;
+
+        assert_eq!(
+            compose.services["worker"],
+            Service {
+                image: Some("owner/worker".to_string()),
+                build: Some(Build::Context("./worker".to_string())),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dockerfile() -> Result<()> {
+        let compose = Compose::parse(COMPOSE)?;
+
+        assert_eq!(
+            compose.dockerfile(&Parser::parse_image("ghcr.io/owner/api:1.0")?),
+            Some("docker/api.Dockerfile".to_string())
+        );
+        assert_eq!(
+            compose.dockerfile(&Parser::parse_image("owner/worker")?),
+            Some("worker/Dockerfile".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dockerfile_without_build() -> Result<()> {
+        let compose = Compose::parse(COMPOSE)?;
+
+        assert_eq!(compose.dockerfile(&Parser::parse_image("postgres")?), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = |name: &str| directory.path().join(name);
+
+        fs::write(path(".env"), "DIS_COMPOSE_TAG=1.2\n")?;
+        fs::write(
+            path("compose.yaml"),
+            r#"
+services:
+  web:
+    image: owner/web:${DIS_COMPOSE_TAG}
+  worker:
+    extends: web
+    command: work
+  cache:
+    extends:
+      file: base.yaml
+      service: redis
+  builder:
+    build: .
+"#,
+        )?;
+        fs::write(
+            path("base.yaml"),
+            "services:\n  redis:\n    image: redis:${DIS_COMPOSE_REDIS:-7}-alpine\n",
+        )?;
+        fs::write(
+            path("compose.prod.yaml"),
+            "services:\n  web:\n    image: ghcr.io/owner/web:$${DIS_COMPOSE_TAG}\n",
+        )?;
+
+        let compose = Compose::load(&[path("compose.yaml")], &HashMap::new())?;
+        assert_eq!(
+            compose.images(),
+            vec![
+                ("cache", "redis:7-alpine"),
+                ("web", "owner/web:1.2"),
+                ("worker", "owner/web:1.2"),
+            ]
+        );
+
+        let shell = HashMap::from([("DIS_COMPOSE_TAG".to_string(), "2.0".to_string())]);
+        let compose = Compose::load(&[path("compose.yaml")], &shell)?;
+        assert_eq!(compose.images()[1], ("web", "owner/web:2.0"));
+
+        let compose = Compose::load(
+            &[path("compose.yaml"), path("compose.prod.yaml")],
+            &HashMap::new(),
+        )?;
+        assert_eq!(
+            compose.images()[1],
+            ("web", "ghcr.io/owner/web:${DIS_COMPOSE_TAG}")
+        );
+        assert_eq!(
+            compose.images()[2],
+            ("worker", "ghcr.io/owner/web:${DIS_COMPOSE_TAG}")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_extends_override() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = |name: &str| directory.path().join(name);
+
+        fs::create_dir(path("prod"))?;
+        fs::write(
+            path("compose.yaml"),
+            "services:\n  web:\n    image: owner/web:1.0\n",
+        )?;
+        fs::write(
+            path("prod/compose.yaml"),
+            "services:\n  cache:\n    extends:\n      file: base.yaml\n      service: redis\n",
+        )?;
+        fs::write(
+            path("base.yaml"),
+            "services:\n  redis:\n    image: redis:6\n",
+        )?;
+        fs::write(
+            path("prod/base.yaml"),
+            "services:\n  redis:\n    image: redis:7\n",
+        )?;
+
+        let compose = Compose::load(
+            &[path("compose.yaml"), path("prod/compose.yaml")],
+            &HashMap::new(),
+        )?;
+        assert_eq!(
+            compose.images(),
+            vec![("cache", "redis:7"), ("web", "owner/web:1.0")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_extends_cycle() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("compose.yaml");
+        fs::write(
+            &path,
+            "services:\n  a:\n    extends: b\n  b:\n    extends:\n      service: a\n",
+        )?;
+
+        assert!(Compose::load(&[path], &HashMap::new()).is_err());
+        Ok(())
+    }
+}
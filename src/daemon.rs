@@ -0,0 +1,550 @@
+use crate::{
+    parser::{ImageReference, Parser},
+    platform::Platform,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use http_body_util::{BodyExt, Empty};
+use hyper::{Request, StatusCode, body::Bytes};
+use hyper_util::rt::TokioIo;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+use tokio::{net::UnixStream, process::Command};
+
+/// Socket of the Docker daemon when neither `DOCKER_HOST` nor a context is set
+pub const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// Socket of the rootful Podman service, rootless services listen in `$XDG_RUNTIME_DIR`
+const PODMAN_SOCKET: &str = "/run/podman/podman.sock";
+
+/// Prefix of the Podman REST API, it also serves the Docker compatible API without it
+const LIBPOD_PREFIX: &str = "/v4.0.0/libpod";
+
+/// Context of the Docker CLI which uses the default socket
+const DEFAULT_CONTEXT: &str = "default";
+
+/// Address of a container engine API
+#[derive(Debug, Clone, PartialEq)]
+pub enum Host {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl Host {
+    /// Parse a `DOCKER_HOST` style address (e.g. "unix:///var/run/docker.sock" or "tcp://localhost:2375")
+    pub fn parse(host: &str) -> Result<Self> {
+        if let Some(path) = host.strip_prefix("unix://") {
+            Ok(Self::Unix(PathBuf::from(path)))
+        } else if let Some(address) = host.strip_prefix("tcp://") {
+            Ok(Self::Tcp(format!(
+                "http://{}",
+                address.trim_end_matches('/')
+            )))
+        } else if host.starts_with("http://") || host.starts_with("https://") {
+            Ok(Self::Tcp(host.trim_end_matches('/').to_string()))
+        } else {
+            bail!("Unsupported daemon host: {host}")
+        }
+    }
+}
+
+/// Container engines to look for local images in, as chosen with `--engine`
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum Engines {
+    /// Try Docker, Podman and then containerd
+    #[default]
+    Auto,
+    Docker,
+    Podman,
+    /// containerd through nerdctl
+    Containerd,
+}
+
+/// Container engine holding the local images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    Docker,
+    Podman,
+    /// containerd through nerdctl
+    Containerd,
+}
+
+impl Engines {
+    /// Engines to look for local images in, in order
+    pub fn engines(self) -> Vec<Engine> {
+        match self {
+            Engines::Auto => vec![Engine::Docker, Engine::Podman, Engine::Containerd],
+            Engines::Docker => vec![Engine::Docker],
+            Engines::Podman => vec![Engine::Podman],
+            Engines::Containerd => vec![Engine::Containerd],
+        }
+    }
+}
+
+impl Engine {
+    /// Inspect a local image in the engine
+    pub async fn inspect(self, image: &str) -> Result<Option<ImageInspect>> {
+        match self {
+            Engine::Docker => Daemon::docker()?.inspect(image).await,
+            Engine::Podman => Daemon::podman()?.inspect(image).await,
+            Engine::Containerd => Self::nerdctl(image).await,
+        }
+    }
+
+    /// Inspect an image in the containerd content store in the Docker format
+    async fn nerdctl(image: &str) -> Result<Option<ImageInspect>> {
+        let output = Command::new("nerdctl")
+            .args(["image", "inspect", "--mode=dockercompat", image])
+            .output()
+            .await
+            .context("Failed to execute nerdctl")?;
+
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        Self::parse_nerdctl(&output.stdout)
+    }
+
+    fn parse_nerdctl(output: &[u8]) -> Result<Option<ImageInspect>> {
+        let images: Vec<ImageInspect> = serde_json::from_slice(output)?;
+        Ok(images.into_iter().next())
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Engine::Docker => "docker",
+            Engine::Podman => "podman",
+            Engine::Containerd => "containerd",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Client of the Docker Engine API or the Podman REST API
+pub struct Daemon {
+    client: Client,
+    host: Host,
+    prefix: &'static str,
+}
+
+/// The fields of `/images/{name}/json` used to find the source of an image
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageInspect {
+    pub repo_digests: Option<Vec<String>>,
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+    pub config: Option<ContainerConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    pub labels: Option<HashMap<String, String>>,
+}
+
+impl ImageInspect {
+    pub fn labels(&self) -> Option<&HashMap<String, String>> {
+        self.config.as_ref()?.labels.as_ref()
+    }
+
+    pub fn platform(&self) -> Platform {
+        Platform {
+            os: self.os.clone(),
+            architecture: self.architecture.clone(),
+            variant: self.variant.clone(),
+        }
+        .normalize()
+    }
+
+    /// Digest the image was pulled by from the repository of the reference
+    pub fn digest(&self, image: &ImageReference) -> Option<String> {
+        self.repo_digests
+            .iter()
+            .flatten()
+            .filter_map(|digest| Parser::parse_image(digest).ok())
+            .find(|digest| digest.same_repository(image))?
+            .digest
+    }
+}
+
+impl Daemon {
+    pub fn new(host: Host) -> Self {
+        Self {
+            client: Client::new(),
+            host,
+            prefix: "",
+        }
+    }
+
+    /// Podman service from `CONTAINER_HOST`, the rootless socket or the rootful socket
+    pub fn podman() -> Result<Self> {
+        let host = match env::var("CONTAINER_HOST") {
+            Ok(host) if !host.is_empty() => Host::parse(&host)?,
+            _ => Host::Unix(Self::podman_socket(
+                env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).as_deref(),
+            )),
+        };
+
+        Ok(Self {
+            prefix: LIBPOD_PREFIX,
+            ..Self::new(host)
+        })
+    }
+
+    /// Daemon the Docker CLI talks to, from `DOCKER_HOST`, `DOCKER_CONTEXT` or the current context
+    pub fn docker() -> Result<Self> {
+        if let Ok(host) = env::var("DOCKER_HOST")
+            && !host.is_empty()
+        {
+            return Ok(Self::new(Host::parse(&host)?));
+        }
+
+        let config = Self::config_directory();
+        let context = env::var("DOCKER_CONTEXT")
+            .ok()
+            .filter(|context| !context.is_empty())
+            .or_else(|| Self::current_context(&config));
+
+        let host = match context {
+            Some(context) if context != DEFAULT_CONTEXT => Self::context_host(&config, &context)?,
+            _ => Host::Unix(PathBuf::from(DOCKER_SOCKET)),
+        };
+
+        Ok(Self::new(host))
+    }
+
+    /// Inspect a local image, `None` if the daemon does not have it
+    pub async fn inspect(&self, image: &str) -> Result<Option<ImageInspect>> {
+        let path = Self::inspect_url(self.prefix, image);
+
+        let (status, body) = match &self.host {
+            Host::Unix(socket) => Self::get(socket, &path).await?,
+            Host::Tcp(address) => {
+                let response = self.client.get(format!("{address}{path}")).send().await?;
+                (response.status(), response.bytes().await?)
+            }
+        };
+
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            bail!("Failed to inspect {image}: {status}");
+        }
+
+        Ok(Some(serde_json::from_slice(&body)?))
+    }
+
+    /// Send a GET request over a Unix socket
+    async fn get(socket: &Path, path: &str) -> Result<(StatusCode, Bytes)> {
+        let stream = UnixStream::connect(socket)
+            .await
+            .with_context(|| format!("Failed to connect to {}", socket.display()))?;
+
+        let (mut sender, connection) =
+            hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(connection);
+
+        let request = Request::get(path)
+            .header("Host", "localhost")
+            .body(Empty::<Bytes>::new())?;
+        let response = sender.send_request(request).await?;
+
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+
+        Ok((status, body))
+    }
+
+    fn podman_socket(runtime: Option<&Path>) -> PathBuf {
+        runtime
+            .map(|runtime| runtime.join("podman").join("podman.sock"))
+            .filter(|socket| socket.exists())
+            .unwrap_or_else(|| PathBuf::from(PODMAN_SOCKET))
+    }
+
+    /// Configuration directory of the Docker CLI
+    fn config_directory() -> PathBuf {
+        match env::var_os("DOCKER_CONFIG") {
+            Some(config) => PathBuf::from(config),
+            None => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".docker"),
+        }
+    }
+
+    fn current_context(config: &Path) -> Option<String> {
+        let contents = fs::read_to_string(config.join("config.json")).ok()?;
+        let config: Value = serde_json::from_str(&contents).ok()?;
+
+        config["currentContext"].as_str().map(String::from)
+    }
+
+    /// Read the Docker endpoint of a context, its metadata is stored under the SHA-256 of its name
+    fn context_host(config: &Path, context: &str) -> Result<Host> {
+        let digest: String = Sha256::digest(context.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let path = config
+            .join("contexts")
+            .join("meta")
+            .join(digest)
+            .join("meta.json");
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Docker context {context} does not exist"))?;
+        let meta: Value = serde_json::from_str(&contents)?;
+
+        let host = meta["Endpoints"]["docker"]["Host"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Docker context {context} has no docker endpoint"))?;
+
+        Host::parse(host)
+    }
+
+    fn inspect_url(prefix: &str, image: &str) -> String {
+        format!("{prefix}/images/{image}/json")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixListener,
+    };
+
+    const IMAGE: &str = "ghcr.io/owner/repo:latest";
+    const DOCKER_PATH: &str = "/images/ghcr.io/owner/repo:latest/json";
+
+    const INSPECT: &str = r#"{
+        "Id": "sha256:0f7b6e9d",
+        "RepoTags": ["ghcr.io/owner/repo:latest"],
+        "RepoDigests": [
+            "ghcr.io/owner/repo@sha256:4a0fb1e6f4f2cbd7e4f6ce3a1bde3b8d0c7a61fb63f4f20e9b6b4d5d3c2b1a09"
+        ],
+        "Os": "linux",
+        "Architecture": "arm64",
+        "Variant": "v8",
+        "Config": {
+            "Labels": {
+                "org.opencontainers.image.source": "https://github.com/owner/repo",
+                "org.opencontainers.image.revision": "abc"
+            }
+        }
+    }"#;
+
+    /// Serve a single response on a Unix socket like the Docker daemon would
+    async fn serve(path: &str, status: &str, body: &'static str) -> Result<(TempDir, PathBuf)> {
+        let directory = TempDir::new()?;
+        let socket = directory.path().join("docker.sock");
+        let listener = UnixListener::bind(&socket)?;
+        let status = status.to_string();
+        let path = path.to_string();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut request = vec![0; 4096];
+            let read = stream.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..read]);
+            assert!(request.starts_with(&format!("GET {path} ")));
+
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        Ok((directory, socket))
+    }
+
+    mod public {
+        use super::*;
+
+        #[test]
+        fn test_host_parse() -> Result<()> {
+            assert_eq!(
+                Host::parse("unix:///run/user/1000/docker.sock")?,
+                Host::Unix(PathBuf::from("/run/user/1000/docker.sock"))
+            );
+            assert_eq!(
+                Host::parse("tcp://127.0.0.1:2375")?,
+                Host::Tcp("http://127.0.0.1:2375".to_string())
+            );
+            assert!(Host::parse("ssh://user@host").is_err());
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_inspect() -> Result<()> {
+            let (_directory, socket) = serve(DOCKER_PATH, "200 OK", INSPECT).await?;
+
+            let inspect = Daemon::new(Host::Unix(socket))
+                .inspect(IMAGE)
+                .await?
+                .expect("image should exist");
+
+            assert_eq!(
+                inspect.labels().unwrap()["org.opencontainers.image.revision"],
+                "abc"
+            );
+            assert_eq!(inspect.platform(), "linux/arm64".parse()?);
+            assert_eq!(
+                inspect.digest(&Parser::parse_image(IMAGE)?).as_deref(),
+                Some("sha256:4a0fb1e6f4f2cbd7e4f6ce3a1bde3b8d0c7a61fb63f4f20e9b6b4d5d3c2b1a09")
+            );
+            assert_eq!(inspect.digest(&Parser::parse_image("owner/repo")?), None);
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_inspect_missing_image() -> Result<()> {
+            let (_directory, socket) = serve(
+                DOCKER_PATH,
+                "404 Not Found",
+                r#"{"message":"No such image: ghcr.io/owner/repo:latest"}"#,
+            )
+            .await?;
+
+            assert!(
+                Daemon::new(Host::Unix(socket))
+                    .inspect(IMAGE)
+                    .await?
+                    .is_none()
+            );
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_inspect_podman() -> Result<()> {
+            let (_directory, socket) = serve(
+                "/v4.0.0/libpod/images/ghcr.io/owner/repo:latest/json",
+                "200 OK",
+                INSPECT,
+            )
+            .await?;
+
+            let daemon = Daemon {
+                prefix: LIBPOD_PREFIX,
+                ..Daemon::new(Host::Unix(socket))
+            };
+
+            assert!(daemon.inspect(IMAGE).await?.is_some());
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_inspect_without_daemon() -> Result<()> {
+            let directory = TempDir::new()?;
+
+            assert!(
+                Daemon::new(Host::Unix(directory.path().join("docker.sock")))
+                    .inspect(IMAGE)
+                    .await
+                    .is_err()
+            );
+            Ok(())
+        }
+    }
+
+    mod private {
+        use super::*;
+
+        #[test]
+        fn test_current_context() -> Result<()> {
+            let config = TempDir::new()?;
+            fs::write(
+                config.path().join("config.json"),
+                r#"{"auths": {}, "currentContext": "colima"}"#,
+            )?;
+
+            assert_eq!(
+                Daemon::current_context(config.path()),
+                Some("colima".to_string())
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_context_host() -> Result<()> {
+            let config = TempDir::new()?;
+            // SHA-256 of "colima"
+            let meta = config.path().join(
+                "contexts/meta/f24fd3749c1368328e2b149bec149cb6795619f244c5b584e844961215dadd16",
+            );
+            fs::create_dir_all(&meta)?;
+            fs::write(
+                meta.join("meta.json"),
+                r#"{"Name":"colima","Endpoints":{"docker":{"Host":"unix:///home/user/.colima/default/docker.sock"}}}"#,
+            )?;
+
+            assert_eq!(
+                Daemon::context_host(config.path(), "colima")?,
+                Host::Unix(PathBuf::from("/home/user/.colima/default/docker.sock"))
+            );
+            assert!(Daemon::context_host(config.path(), "missing").is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn test_podman_socket() -> Result<()> {
+            let runtime = TempDir::new()?;
+
+            assert_eq!(
+                Daemon::podman_socket(Some(runtime.path())),
+                PathBuf::from(PODMAN_SOCKET)
+            );
+
+            fs::create_dir(runtime.path().join("podman"))?;
+            fs::write(runtime.path().join("podman/podman.sock"), "")?;
+
+            assert_eq!(
+                Daemon::podman_socket(Some(runtime.path())),
+                runtime.path().join("podman/podman.sock")
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_engines() {
+            assert_eq!(
+                Engines::Auto.engines(),
+                vec![Engine::Docker, Engine::Podman, Engine::Containerd]
+            );
+            assert_eq!(Engines::Podman.engines(), vec![Engine::Podman]);
+        }
+
+        #[test]
+        fn test_parse_nerdctl() -> Result<()> {
+            let output = format!("[{INSPECT}]");
+            let inspect = Engine::parse_nerdctl(output.as_bytes())?.expect("image should exist");
+
+            assert_eq!(inspect.platform(), "linux/arm64".parse()?);
+            assert!(Engine::parse_nerdctl(b"[]")?.is_none());
+            Ok(())
+        }
+
+        #[test]
+        fn test_inspect_url() {
+            assert_eq!(Daemon::inspect_url("", IMAGE), DOCKER_PATH);
+            assert_eq!(
+                Daemon::inspect_url(LIBPOD_PREFIX, IMAGE),
+                "/v4.0.0/libpod/images/ghcr.io/owner/repo:latest/json"
+            );
+        }
+    }
+}
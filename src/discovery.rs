@@ -0,0 +1,211 @@
+use std::cmp::Reverse;
+
+/// Directories which hold Dockerfiles that do not build the published image
+const IGNORED_DIRECTORIES: [&str; 8] = [
+    "test",
+    "tests",
+    "testdata",
+    "example",
+    "examples",
+    "fixtures",
+    "vendor",
+    "node_modules",
+];
+
+/// Directories conventionally used for the Dockerfiles of a project
+const DOCKER_DIRECTORIES: [&str; 3] = ["docker", ".docker", "build"];
+
+pub struct Discovery;
+
+/// A Dockerfile found in a repository with its ranking against the image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub path: String,
+    pub score: i32,
+}
+
+impl Discovery {
+    /// Check if a path names a Dockerfile (Dockerfile, *.Dockerfile, Dockerfile.* or Containerfile)
+    pub fn dockerfile(path: &str) -> bool {
+        let file = path.rsplit('/').next().unwrap_or(path);
+
+        file == "Dockerfile"
+            || file == "Containerfile"
+            || file.starts_with("Dockerfile.")
+            || file.starts_with("Containerfile.")
+            || file.ends_with(".Dockerfile")
+            || file.ends_with(".dockerfile")
+    }
+
+    /// Join a Dockerfile path onto its directory as a normalized path relative to the root
+    ///
+    /// Leading "./", empty and "." components are dropped and ".." pops a directory.
+    pub fn join(directory: &str, file: &str) -> String {
+        let mut components: Vec<&str> = vec![];
+
+        for component in directory.split('/').chain(file.split('/')) {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    components.pop();
+                }
+                component => components.push(component),
+            }
+        }
+
+        components.join("/")
+    }
+
+    /// Rank the Dockerfiles among the paths by how well they match the image name, best first
+    pub fn rank(paths: &[String], image: &str) -> Vec<Candidate> {
+        let mut candidates: Vec<Candidate> = paths
+            .iter()
+            .filter(|path| Self::dockerfile(path))
+            .map(|path| Candidate {
+                path: path.clone(),
+                score: Self::score(path, image),
+            })
+            .collect();
+
+        candidates.sort_by_key(|candidate| {
+            (
+                Reverse(candidate.score),
+                candidate.path.len(),
+                candidate.path.clone(),
+            )
+        });
+
+        candidates
+    }
+
+    /// Score a Dockerfile path for an image name
+    ///
+    /// Plain Dockerfiles at the root score best unless a directory or suffix names the image,
+    /// e.g. `services/<image>/Dockerfile` or `Dockerfile.<image>` in a monorepo.
+    fn score(path: &str, image: &str) -> i32 {
+        let mut components: Vec<&str> = path.split('/').collect();
+        let file = components.pop().unwrap_or(path);
+        let directories = components;
+
+        let qualifier = file
+            .strip_prefix("Dockerfile.")
+            .or_else(|| file.strip_prefix("Containerfile."))
+            .or_else(|| file.strip_suffix(".Dockerfile"))
+            .or_else(|| file.strip_suffix(".dockerfile"));
+
+        let mut score = match qualifier {
+            None => 10,
+            Some(qualifier) if qualifier == image => 30,
+            Some(qualifier) if qualifier.contains(image) => 15,
+            Some(_) => 5,
+        };
+
+        if directories.last() == Some(&image) {
+            score += 30;
+        } else if directories.contains(&image) {
+            score += 15;
+        }
+
+        if directories
+            .iter()
+            .any(|directory| DOCKER_DIRECTORIES.contains(directory))
+        {
+            score += 3;
+        }
+
+        if directories
+            .iter()
+            .any(|directory| IGNORED_DIRECTORIES.contains(directory))
+        {
+            score -= 20;
+        }
+
+        // Prefer Dockerfiles closer to the root
+        score - 2 * directories.len() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    fn ranked(paths: &[String], image: &str) -> Vec<String> {
+        Discovery::rank(paths, image)
+            .into_iter()
+            .map(|candidate| candidate.path)
+            .collect()
+    }
+
+    #[test]
+    fn test_dockerfile() {
+        assert!(Discovery::dockerfile("Dockerfile"));
+        assert!(Discovery::dockerfile("docker/Dockerfile.alpine"));
+        assert!(Discovery::dockerfile("services/api/api.Dockerfile"));
+        assert!(Discovery::dockerfile("Containerfile"));
+        assert!(!Discovery::dockerfile("docs/Dockerfile.md/README"));
+        assert!(!Discovery::dockerfile("dockerfile-lint.yml"));
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(Discovery::join(".", "Dockerfile"), "Dockerfile");
+        assert_eq!(Discovery::join("./app/", "./Dockerfile"), "app/Dockerfile");
+        assert_eq!(
+            Discovery::join("services/api", "../docker/api.Dockerfile"),
+            "services/docker/api.Dockerfile"
+        );
+    }
+
+    #[test]
+    fn test_rank_root() {
+        let tree = paths(&[
+            "README.md",
+            "Dockerfile.alltools",
+            "Dockerfile",
+            "cmd/geth/main.go",
+        ]);
+
+        assert_eq!(
+            ranked(&tree, "client-go"),
+            vec!["Dockerfile", "Dockerfile.alltools"]
+        );
+    }
+
+    #[test]
+    fn test_rank_monorepo() {
+        let tree = paths(&[
+            "Dockerfile",
+            "services/api/Dockerfile",
+            "services/worker/Dockerfile",
+        ]);
+
+        assert_eq!(ranked(&tree, "worker")[0], "services/worker/Dockerfile");
+    }
+
+    #[test]
+    fn test_rank_qualifier() {
+        let tree = paths(&["Dockerfile", "docker/Dockerfile.worker", "api.Dockerfile"]);
+
+        assert_eq!(ranked(&tree, "worker")[0], "docker/Dockerfile.worker");
+        assert_eq!(ranked(&tree, "api")[0], "api.Dockerfile");
+    }
+
+    #[test]
+    fn test_rank_ignored_directories() {
+        let tree = paths(&["tests/Dockerfile", "docker/Dockerfile"]);
+
+        assert_eq!(
+            ranked(&tree, "foundry"),
+            vec!["docker/Dockerfile", "tests/Dockerfile"]
+        );
+    }
+
+    #[test]
+    fn test_rank_no_dockerfiles() {
+        assert!(Discovery::rank(&paths(&["README.md", "src/main.rs"]), "image").is_empty());
+    }
+}
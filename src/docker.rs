@@ -1,5 +1,5 @@
-use reqwest::Client;
-use std::process::Command;
+use anyhow::Result;
+use reqwest::{Client, StatusCode};
 
 pub const DEFAULT_SOURCE: &str = "org.opencontainers.image.source";
 pub const DEFAULT_REVISION: &str = "org.opencontainers.image.revision";
@@ -17,34 +17,16 @@ impl Docker {
         }
     }
 
-    pub fn inspect(image: &str, label: &str) -> Option<String> {
-        let output = Command::new("docker")
-            .args([
-                "inspect",
-                "--format",
-                &format!("{{{{index .Config.Labels \"{}\"}}}}", label),
-                image,
-            ])
-            .output()
-            .ok()?;
-
-        if output.status.success() {
-            let label_value = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if !label_value.is_empty() {
-                return Some(label_value);
-            }
-        }
-
-        None
-    }
-
-    pub async fn repo_exists(&self, namespace: &str, repo: &str) -> bool {
+    pub async fn repo_exists(&self, namespace: &str, repo: &str) -> Result<bool> {
         let url = Self::api_url(namespace, repo);
 
-        match self.client.head(&url).send().await {
-            Ok(response) => response.status().is_success(),
-            Err(_) => false,
+        let response = self.client.head(&url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
         }
+
+        response.error_for_status()?;
+        Ok(true)
     }
 
     pub fn web_url(namespace: Option<&str>, repo: &str) -> String {
@@ -78,8 +60,7 @@ mod tests {
     mod public {
         use super::*;
 
-        // TODO: unsure how to test Docker::inspect() while using an image that doesn't exist. Need to mock somehow without changing code above
-        // TODO: similarly, Docker::repo_exists() needs to be mocked and resolve to some mock server
+        // TODO: Docker::repo_exists() needs to be mocked and resolve to some mock server
 
         #[test]
         fn test_web_url_namespace() {
@@ -0,0 +1,539 @@
+use crate::{
+    platform::Platform,
+    variables::{DOCKERFILE, expand},
+};
+use anyhow::{Result, bail};
+use std::{collections::HashMap, iter};
+
+/// Base of a stage which starts from an empty filesystem
+pub const SCRATCH: &str = "scratch";
+
+/// Escape character of a Dockerfile unless changed by the `escape` parser directive
+const DEFAULT_ESCAPE: char = '\\';
+
+/// Prefixes of the platform arguments, for the platform building and the platform built for
+const PLATFORM_PREFIXES: [&str; 2] = ["BUILD", "TARGET"];
+
+/// Suffixes of the platform arguments, for each part of the platform
+const PLATFORM_SUFFIXES: [&str; 4] = ["PLATFORM", "OS", "ARCH", "VARIANT"];
+
+/// A Dockerfile reduced to the stages it builds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dockerfile {
+    pub stages: Vec<Stage>,
+}
+
+/// A build stage started by a `FROM` instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    /// Image or earlier stage built from, with build arguments expanded
+    pub base: String,
+    /// Name given with `AS`
+    pub name: Option<String>,
+    /// Platform given with `--platform`, with build arguments expanded
+    pub platform: Option<String>,
+    /// Images or stages copied from with `COPY --from` or mounted with `RUN --mount=from=`
+    pub dependencies: Vec<String>,
+}
+
+impl Dockerfile {
+    /// Parse the stages of a Dockerfile
+    ///
+    /// `FROM` can only use the arguments declared before the first stage, their defaults are
+    /// overridden by `args` (e.g. build arguments or the platform arguments). The instructions
+    /// of a stage only see the arguments the stage declares, a global one declared again by name
+    /// keeps its global value.
+    pub fn parse(contents: &str, args: &HashMap<String, String>) -> Result<Self> {
+        let mut globals = Self::automatic(args);
+        let mut scope = HashMap::new();
+        let mut stages: Vec<Stage> = vec![];
+
+        for line in Self::instructions(contents) {
+            let (instruction, arguments) = line
+                .split_once(char::is_whitespace)
+                .unwrap_or((line.as_str(), ""));
+
+            match instruction.to_ascii_uppercase().as_str() {
+                "ARG" => {
+                    for declaration in arguments.split_whitespace() {
+                        let (name, default) = match declaration.split_once('=') {
+                            Some((name, default)) => (name, Some(default)),
+                            None => (declaration, None),
+                        };
+
+                        let visible = if stages.is_empty() { &globals } else { &scope };
+                        let value = match (args.get(name), default) {
+                            (Some(value), _) => value.clone(),
+                            (None, Some(default)) => expand(unquote(default), visible, DOCKERFILE)?,
+                            (None, None) => match globals.get(name) {
+                                Some(value) => value.clone(),
+                                None => continue,
+                            },
+                        };
+
+                        if stages.is_empty() {
+                            globals.insert(name.to_string(), value);
+                        } else {
+                            scope.insert(name.to_string(), value);
+                        }
+                    }
+                }
+                "FROM" => {
+                    stages.push(Self::stage(arguments, &globals)?);
+                    scope.clear();
+                }
+                "COPY" | "RUN" => {
+                    if let Some(stage) = stages.last_mut() {
+                        stage
+                            .dependencies
+                            .extend(Self::dependencies(arguments, &scope)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self { stages })
+    }
+
+    /// Images the stages are built from in order without duplicates, stages built from
+    /// earlier stages are skipped
+    pub fn bases(&self) -> Vec<&str> {
+        let mut bases: Vec<&str> = vec![];
+
+        for (index, stage) in self.stages.iter().enumerate() {
+            if !Self::named(&self.stages[..index], &stage.base)
+                && !bases.contains(&stage.base.as_str())
+            {
+                bases.push(&stage.base);
+            }
+        }
+
+        bases
+    }
+
+    /// Images the stages are built from, copy from or mount in order without duplicates
+    ///
+    /// Earlier stages, referenced by name or index, and scratch are not images.
+    pub fn images(&self) -> Vec<&str> {
+        let mut images: Vec<&str> = vec![];
+
+        for (index, stage) in self.stages.iter().enumerate() {
+            // Stages can not copy from themselves but the name does not refer to an image either
+            let base = iter::once(&stage.base).map(|base| (base, index));
+            let dependencies = stage
+                .dependencies
+                .iter()
+                .map(|dependency| (dependency, index + 1));
+
+            for (reference, named) in base.chain(dependencies) {
+                if !Self::named(&self.stages[..named], reference)
+                    && reference.parse::<usize>().is_err()
+                    && reference != SCRATCH
+                    && !images.contains(&reference.as_str())
+                {
+                    images.push(reference);
+                }
+            }
+        }
+
+        images
+    }
+
+    /// Check if one of the stages is named by the reference
+    fn named(stages: &[Stage], reference: &str) -> bool {
+        stages.iter().any(|stage| {
+            stage
+                .name
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(reference))
+        })
+    }
+
+    /// Arguments BuildKit defines for the platform of the build
+    pub fn platform_args(platform: &Platform) -> HashMap<String, String> {
+        let mut args = HashMap::new();
+
+        let values = [
+            platform.to_string(),
+            platform.os.clone(),
+            platform.architecture.clone(),
+            platform.variant.clone().unwrap_or_default(),
+        ];
+
+        for prefix in PLATFORM_PREFIXES {
+            for (suffix, value) in PLATFORM_SUFFIXES.iter().zip(&values) {
+                args.insert(format!("{prefix}{suffix}"), value.clone());
+            }
+        }
+
+        args
+    }
+
+    /// Platform arguments are available to `FROM` without being declared
+    fn automatic(args: &HashMap<String, String>) -> HashMap<String, String> {
+        args.iter()
+            .filter(|(name, _)| {
+                PLATFORM_PREFIXES.iter().any(|prefix| {
+                    name.strip_prefix(prefix)
+                        .is_some_and(|suffix| PLATFORM_SUFFIXES.contains(&suffix))
+                })
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Parse `[--platform=<platform>] <image> [AS <name>]`
+    fn stage(arguments: &str, globals: &HashMap<String, String>) -> Result<Stage> {
+        let mut platform = None;
+        let mut words = arguments.split_whitespace().peekable();
+
+        while let Some(flag) = words.next_if(|word| word.starts_with("--")) {
+            if let Some(value) = flag.strip_prefix("--platform=") {
+                platform = Some(expand(value, globals, DOCKERFILE)?);
+            }
+        }
+
+        let Some(base) = words.next() else {
+            bail!("FROM {arguments} has no image");
+        };
+
+        let name = match (words.next(), words.next()) {
+            (Some(keyword), Some(name)) if keyword.eq_ignore_ascii_case("AS") => {
+                Some(name.to_string())
+            }
+            (None, _) => None,
+            _ => bail!("FROM {arguments} is not followed by AS <name>"),
+        };
+
+        Ok(Stage {
+            base: expand(base, globals, DOCKERFILE)?,
+            name,
+            platform,
+            dependencies: vec![],
+        })
+    }
+
+    /// Parse the `--from=<image>` and `--mount=...,from=<image>` flags of `COPY` and `RUN`
+    fn dependencies(arguments: &str, scope: &HashMap<String, String>) -> Result<Vec<String>> {
+        arguments
+            .split_whitespace()
+            .take_while(|word| word.starts_with("--"))
+            .filter_map(|flag| match flag.strip_prefix("--from=") {
+                Some(from) => Some(from),
+                None => flag
+                    .strip_prefix("--mount=")?
+                    .split(',')
+                    .find_map(|option| option.strip_prefix("from=")),
+            })
+            .map(|from| expand(from, scope, DOCKERFILE))
+            .filter(|from| !from.as_ref().is_ok_and(String::is_empty))
+            .collect()
+    }
+
+    /// Join continued lines into whole instructions, skipping comments, blank lines and the
+    /// bodies of heredocs
+    fn instructions(contents: &str) -> Vec<String> {
+        let mut escape = DEFAULT_ESCAPE;
+        let mut directives = true;
+        let mut instructions = vec![];
+        let mut current = String::new();
+        let mut heredocs: Vec<String> = vec![];
+
+        for line in contents.lines() {
+            let trimmed = line.trim();
+
+            // Heredoc bodies are read in order until the line with their delimiter
+            if let Some(delimiter) = heredocs.first() {
+                if trimmed == delimiter {
+                    heredocs.remove(0);
+                }
+                continue;
+            }
+
+            // Parser directives are only read before anything else
+            if directives {
+                match trimmed
+                    .strip_prefix('#')
+                    .and_then(|directive| directive.split_once('='))
+                {
+                    Some((key, value)) if key.trim().eq_ignore_ascii_case("escape") => {
+                        escape = value.trim().chars().next().unwrap_or(DEFAULT_ESCAPE);
+                        continue;
+                    }
+                    Some(_) => continue,
+                    None => directives = false,
+                }
+            }
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            match trimmed.strip_suffix(escape) {
+                Some(continued) => {
+                    current.push_str(continued);
+                    current.push(' ');
+                }
+                None => {
+                    current.push_str(trimmed);
+                    heredocs = Self::heredocs(&current);
+                    instructions.push(std::mem::take(&mut current));
+                }
+            }
+        }
+
+        if !current.trim().is_empty() {
+            instructions.push(current);
+        }
+
+        instructions
+    }
+
+    /// Delimiters of the heredocs a `RUN`, `COPY` or `ADD` starts (e.g. `<<EOF`, `<<-EOF`
+    /// or `<<"EOF"`) in the order their bodies follow
+    fn heredocs(instruction: &str) -> Vec<String> {
+        let keyword = instruction.split_whitespace().next().unwrap_or_default();
+        if !["RUN", "COPY", "ADD"]
+            .iter()
+            .any(|heredocs| keyword.eq_ignore_ascii_case(heredocs))
+        {
+            return vec![];
+        }
+
+        instruction
+            .split_whitespace()
+            .filter_map(|word| {
+                let (_, delimiter) = word.split_once("<<")?;
+                let delimiter = unquote(delimiter.strip_prefix('-').unwrap_or(delimiter));
+                let valid = delimiter.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && delimiter
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_');
+                valid.then(|| delimiter.to_string())
+            })
+            .collect()
+    }
+}
+
+/// Remove the quotes around a value
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .iter()
+        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    mod public {
+        use super::*;
+
+        #[test]
+        fn test_parse() -> Result<()> {
+            let contents = r#"# syntax=docker/dockerfile:1
+ARG NODE_VERSION=20
+ARG VARIANT="slim"
+FROM --platform=$BUILDPLATFORM node:${NODE_VERSION}-${VARIANT} AS build
+ARG NODE_VERSION=18
+RUN npm ci
+
+FROM gcr.io/distroless/nodejs${NODE_VERSION}-debian12
+COPY --from=build /app /app
+"#;
+
+            let dockerfile =
+                Dockerfile::parse(contents, &args(&[("BUILDPLATFORM", "linux/amd64")]))?;
+            assert_eq!(
+                dockerfile.stages,
+                vec![
+                    Stage {
+                        base: "node:20-slim".to_string(),
+                        name: Some("build".to_string()),
+                        platform: Some("linux/amd64".to_string()),
+                        dependencies: vec![],
+                    },
+                    Stage {
+                        base: "gcr.io/distroless/nodejs20-debian12".to_string(),
+                        name: None,
+                        platform: None,
+                        dependencies: vec!["build".to_string()],
+                    },
+                ]
+            );
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_parse_args_override_defaults() -> Result<()> {
+            let contents = "ARG VERSION=1.0\nARG UNDECLARED\nFROM owner/repo:$VERSION$UNDECLARED";
+
+            let dockerfile = Dockerfile::parse(contents, &args(&[("VERSION", "2.0")]))?;
+            assert_eq!(dockerfile.bases(), vec!["owner/repo:2.0"]);
+
+            // Arguments which are not declared are not available to FROM
+            let dockerfile = Dockerfile::parse(
+                "FROM owner/repo:${VERSION:-1.0}",
+                &args(&[("VERSION", "2.0")]),
+            )?;
+            assert_eq!(dockerfile.bases(), vec!["owner/repo:1.0"]);
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_parse_continuation_and_escape() -> Result<()> {
+            let contents = "# escape=`\n\n# base image\nFROM `\n  # comment within the instruction\n  mcr.microsoft.com/windows/servercore:ltsc2022 `\n  AS base\n";
+
+            let dockerfile = Dockerfile::parse(contents, &HashMap::new())?;
+            assert_eq!(
+                dockerfile.stages,
+                vec![Stage {
+                    base: "mcr.microsoft.com/windows/servercore:ltsc2022".to_string(),
+                    name: Some("base".to_string()),
+                    platform: None,
+                    dependencies: vec![],
+                }]
+            );
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_parse_without_image() {
+            assert!(Dockerfile::parse("FROM --platform=linux/amd64", &HashMap::new()).is_err());
+            assert!(Dockerfile::parse("FROM alpine base", &HashMap::new()).is_err());
+        }
+
+        #[test]
+        fn test_bases() -> Result<()> {
+            let contents =
+                "FROM golang:1.22 AS Build\nFROM build AS test\nFROM scratch\nFROM golang:1.22\n";
+
+            let dockerfile = Dockerfile::parse(contents, &HashMap::new())?;
+            assert_eq!(dockerfile.bases(), vec!["golang:1.22", SCRATCH]);
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_images() -> Result<()> {
+            let contents = r#"ARG TOOLS=ghcr.io/owner/tools:1.0
+FROM golang:1.22 AS build
+ARG TOOLS
+COPY --from=build /src /src
+RUN --mount=type=cache,target=/root/.cache --mount=type=bind,from=${TOOLS},target=/tools make
+FROM scratch
+COPY --from=build /app /app
+COPY --from=0 /etc/ssl /etc/ssl
+COPY --link --from=alpine:3 /bin/sh /bin/sh
+COPY --from=golang:1.22 /usr/local/go /go
+"#;
+
+            let dockerfile = Dockerfile::parse(contents, &HashMap::new())?;
+            assert_eq!(
+                dockerfile.images(),
+                vec!["golang:1.22", "ghcr.io/owner/tools:1.0", "alpine:3"]
+            );
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_images_scoped_args() -> Result<()> {
+            let contents = r#"ARG TOOLS=owner/tools:1.0
+ARG CACHE=owner/cache:1.0
+FROM golang:1.22
+COPY --from=${CACHE} /cache /cache
+ARG TOOLS
+ARG LINTER=owner/linter:1.0
+COPY --from=$TOOLS /tools /tools
+RUN --mount=type=bind,from=${LINTER},target=/linter lint
+"#;
+
+            // Global arguments are only visible in a stage after it declares them again
+            let dockerfile = Dockerfile::parse(contents, &HashMap::new())?;
+            assert_eq!(
+                dockerfile.images(),
+                vec!["golang:1.22", "owner/tools:1.0", "owner/linter:1.0"]
+            );
+
+            // Build arguments override the defaults of stages too
+            let dockerfile = Dockerfile::parse(contents, &args(&[("LINTER", "owner/linter:2.0")]))?;
+            assert_eq!(
+                dockerfile.images(),
+                vec!["golang:1.22", "owner/tools:1.0", "owner/linter:2.0"]
+            );
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_parse_heredocs() -> Result<()> {
+            let contents = r#"FROM alpine:3
+RUN <<EOF cat > /etc/motd && cat <<-'END' > /etc/issue
+FROM ubuntu:24.04
+EOF
+COPY --from=debian:12 / /
+  END
+RUN echo $((1<<2))
+COPY <<"CONFIG" /etc/app.conf
+FROM debian:12
+CONFIG
+FROM golang:1.22
+"#;
+
+            let dockerfile = Dockerfile::parse(contents, &HashMap::new())?;
+            assert_eq!(dockerfile.images(), vec!["alpine:3", "golang:1.22"]);
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_platform_args() -> Result<()> {
+            let args = Dockerfile::platform_args(&"linux/arm/v7".parse()?);
+
+            assert_eq!(args["TARGETPLATFORM"], "linux/arm/v7");
+            assert_eq!(args["BUILDARCH"], "arm");
+            assert_eq!(args["TARGETVARIANT"], "v7");
+
+            Ok(())
+        }
+    }
+
+    mod private {
+        use super::*;
+
+        #[test]
+        fn test_automatic() {
+            let automatic = Dockerfile::automatic(&args(&[
+                ("BUILDPLATFORM", "linux/amd64"),
+                ("TARGETARCH", "arm64"),
+                ("BUILD_ID", "42"),
+                ("TARGET_ENV", "prod"),
+            ]));
+
+            assert_eq!(
+                automatic,
+                args(&[("BUILDPLATFORM", "linux/amd64"), ("TARGETARCH", "arm64")])
+            );
+        }
+
+        #[test]
+        fn test_unquote() {
+            assert_eq!(unquote("\"1.0\""), "1.0");
+            assert_eq!(unquote("'1.0'"), "1.0");
+            assert_eq!(unquote("\"1.0"), "\"1.0");
+        }
+    }
+}
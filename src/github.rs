@@ -1,21 +1,96 @@
-use crate::docker::{DEFAULT_REVISION, OLD_REVISION};
-use anyhow::{Result, anyhow};
-use reqwest::{Client, StatusCode, header};
+use crate::{
+    bake::{BAKE_FILES, Bake},
+    compose::Compose,
+    docker::{DEFAULT_REVISION, OLD_REVISION},
+    registry::Registry,
+    resolution::RevisionKind,
+    trace::Trace,
+    workflow::{Build, Workflow},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use futures::future::join_all;
+use reqwest::{Client, StatusCode};
 use serde::Deserialize;
 use serde_json::Value;
-use std::collections::HashMap;
+
+/// Host of the GitHub container registry
+pub const REGISTRY: &str = "ghcr.io";
+
+/// Base URL of the GitHub REST API
+const API: &str = "https://api.github.com";
+
+/// Base URL of the raw contents of files
+const RAW: &str = "https://raw.githubusercontent.com";
+
+/// Kinds of git refs a reference may name, tags take precedence over branches
+const REF_KINDS: [(&str, RevisionKind); 2] =
+    [("tags", RevisionKind::Tag), ("heads", RevisionKind::Branch)];
+
+/// Maximum number of annotated tags followed before giving up (tags can point at tags)
+const MAX_TAG_DEPTH: usize = 5;
+
+#[derive(Deserialize)]
+struct TreeResponse {
+    tree: Vec<TreeEntry>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+/// Paths of the files of a repository
+pub struct Tree {
+    pub paths: Vec<String>,
+    /// GitHub lists at most 100,000 entries of a recursive tree, the rest are left out
+    pub truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct TreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct GitRef {
+    object: GitObject,
+}
+
+#[derive(Deserialize)]
+struct GitObject {
+    sha: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
 
 pub struct Github {
     client: Client,
+    api: String,
+    raw: String,
+}
+
+impl Default for Github {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Github {
     pub fn new() -> Self {
         Self {
             client: Client::new(),
+            api: API.to_string(),
+            raw: RAW.to_string(),
         }
     }
 
+    /// Send API and raw file requests to a mock server instead of GitHub
+    #[cfg(test)]
+    pub fn with_api(mut self, api: &str) -> Self {
+        self.api = api.trim_end_matches('/').to_string();
+        self.raw = self.api.clone();
+        self
+    }
+
     /// Validate the revision to be a SHA-1 hash (40 characters of hex)
     pub fn sha(rev: &str) -> bool {
         if rev.len() != 40 || !rev.chars().all(|c| c.is_ascii_hexdigit()) {
@@ -52,29 +127,27 @@ impl Github {
         Ok((parts[0].to_string(), parts[1].to_string()))
     }
 
-    /// Check if a repository exists and return the default branch
-    ///
-    /// SAFETY: "default_branch" is in response
-    pub async fn check_repo(&self, owner: &str, repo: &str) -> Option<String> {
-        let url = Self::repo_url(owner, repo);
+    /// Check if a repository exists and return the default branch, `None` if it does not exist
+    pub async fn check_repo(&self, owner: &str, repo: &str) -> Result<Option<String>> {
+        let url = self.repo_url(owner, repo);
 
-        match self
+        let response = self
             .client
             .get(&url)
             .header("User-Agent", "Rust Binary")
             .send()
-            .await
-        {
-            Ok(response) => {
-                let branch = match response.json::<Value>().await {
-                    Ok(json) => json["default_branch"].as_str().map(|s| s.to_string()),
-                    Err(_) => None,
-                };
-
-                branch
-            }
-            Err(_) => None,
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
         }
+
+        let json = response.error_for_status()?.json::<Value>().await?;
+        let branch = json["default_branch"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Repository {owner}/{repo} has no default branch"))?;
+
+        Ok(Some(branch.to_string()))
     }
 
     /// Check if a file exists at a given path and branch
@@ -85,7 +158,7 @@ impl Github {
         path: &str,
         ref_: &str,
     ) -> Result<bool> {
-        let url = Self::file_url(owner, repo, path, ref_);
+        let url = self.file_url(owner, repo, path, ref_);
 
         // TODO: can this return a success for a file that does not exist
         let response = self
@@ -95,174 +168,312 @@ impl Github {
             .send()
             .await?;
 
-        Ok(response.status().is_success())
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        response.error_for_status()?;
+        Ok(true)
     }
 
-    /// Format a GitHub URL for a file at a given revision
-    pub fn web_url(owner: &str, repo: &str, file_path: &str, revision: &str) -> String {
-        format!("https://github.com/{owner}/{repo}/blob/{revision}/{file_path}")
+    /// List the paths of every file in the repository at a revision
+    pub async fn tree(&self, owner: &str, repo: &str, revision: &str) -> Result<Tree> {
+        let tree = self
+            .client
+            .get(self.tree_url(owner, repo, revision))
+            .header("User-Agent", "Rust Binary")
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TreeResponse>()
+            .await?;
+
+        Ok(Tree {
+            paths: tree
+                .tree
+                .into_iter()
+                .filter(|entry| entry.kind == "blob")
+                .map(|entry| entry.path)
+                .collect(),
+            truncated: tree.truncated,
+        })
     }
 
-    fn repo_url(owner: &str, repo: &str) -> String {
-        format!("https://api.github.com/repos/{owner}/{repo}")
+    /// Download the contents of a file at a revision
+    pub async fn raw(&self, owner: &str, repo: &str, path: &str, revision: &str) -> Result<String> {
+        let contents = self
+            .client
+            .get(self.raw_url(owner, repo, path, revision))
+            .header("User-Agent", "Rust Binary")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(contents)
     }
 
-    fn file_url(owner: &str, repo: &str, path: &str, ref_: &str) -> String {
-        format!("https://api.github.com/repos/{owner}/{repo}/contents/{path}?ref={ref_}")
+    /// Fetch and parse the image builds of every workflow in the tree
+    pub async fn workflow_builds(
+        &self,
+        owner: &str,
+        repo: &str,
+        revision: &str,
+        tree: &[String],
+        trace: &Trace,
+    ) -> Vec<Build> {
+        let repository = format!("{owner}/{repo}");
+
+        self.files(
+            owner,
+            repo,
+            revision,
+            tree.iter().filter(|path| Workflow::workflow(path)),
+            |_, workflow| Workflow::builds(workflow, &repository),
+            trace,
+        )
+        .await
+        .into_iter()
+        .flat_map(|(_, builds)| builds)
+        .collect()
     }
 
-    fn ghcr_token_url(owner: &str, repo: &str) -> String {
-        format!("https://ghcr.io/token?service=ghcr.io&scope=repository:{owner}/{repo}:pull")
+    /// Fetch and parse every bake file in the tree with the directory it is in
+    ///
+    /// The bake files of a directory are merged in the order bake reads them, so override files
+    /// take precedence.
+    pub async fn bakes(
+        &self,
+        owner: &str,
+        repo: &str,
+        revision: &str,
+        tree: &[String],
+        trace: &Trace,
+    ) -> Vec<(String, Bake)> {
+        let mut paths: Vec<&String> = tree.iter().filter(|path| Bake::bake_file(path)).collect();
+        paths.sort_by_key(|path| {
+            let (directory, file) = path.rsplit_once('/').unwrap_or(("", path));
+            let order = BAKE_FILES.iter().position(|bake_file| *bake_file == file);
+            (directory, order)
+        });
+
+        let files = self
+            .files(owner, repo, revision, paths.into_iter(), Bake::parse, trace)
+            .await;
+
+        let mut bakes: Vec<(String, Bake)> = vec![];
+        for (directory, bake) in files {
+            match bakes.last_mut() {
+                Some((last, merged)) if *last == directory => merged.merge(bake),
+                _ => bakes.push((directory, bake)),
+            }
+        }
+
+        bakes
     }
 
-    fn ghcr_manifest_url(owner: &str, repo: &str, reference: &str) -> String {
-        format!("https://ghcr.io/v2/{owner}/{repo}/manifests/{reference}")
+    /// Fetch and parse every compose file in the tree with the directory it is in
+    pub async fn composes(
+        &self,
+        owner: &str,
+        repo: &str,
+        revision: &str,
+        tree: &[String],
+        trace: &Trace,
+    ) -> Vec<(String, Compose)> {
+        self.files(
+            owner,
+            repo,
+            revision,
+            tree.iter().filter(|path| Compose::compose_file(path)),
+            |_, contents| Compose::parse(contents),
+            trace,
+        )
+        .await
     }
 
-    fn ghcr_blob_by_digest_url(owner: &str, repo: &str, digest: &str) -> String {
-        format!("https://ghcr.io/v2/{owner}/{repo}/blobs/{digest}")
+    /// Fetch and parse files of the repository with the directory each is in
+    ///
+    /// Files are downloaded concurrently but kept in the order of `paths`. Files which fail to
+    /// download or parse are recorded in the trace and skipped.
+    async fn files<'a, T>(
+        &self,
+        owner: &str,
+        repo: &str,
+        revision: &str,
+        paths: impl Iterator<Item = &'a String>,
+        parse: impl Fn(&str, &str) -> Result<T>,
+        trace: &Trace,
+    ) -> Vec<(String, T)> {
+        let paths: Vec<&String> = paths.collect();
+        let downloads = join_all(
+            paths
+                .iter()
+                .map(|path| self.raw(owner, repo, path, revision)),
+        )
+        .await;
+
+        let mut files = vec![];
+        for (path, contents) in paths.into_iter().zip(downloads) {
+            let action = format!("{path} in {owner}/{repo} at {revision}");
+            let file =
+                contents.and_then(|contents| parse(path, &contents).context("Failed to parse"));
+
+            match file {
+                Ok(file) => {
+                    trace.record(action, "parsed");
+                    let directory = path.rsplit_once('/').map_or("", |(directory, _)| directory);
+                    files.push((directory.to_string(), file));
+                }
+                Err(error) => trace.error(action, &error),
+            }
+        }
+
+        files
     }
 
-    pub async fn revision(
+    /// Format a GitHub URL for a file at a given revision
+    pub fn web_url(owner: &str, repo: &str, file_path: &str, revision: &str) -> String {
+        format!("https://github.com/{owner}/{repo}/blob/{revision}/{file_path}")
+    }
+
+    /// Resolve a tag or branch to the commit SHA it points at
+    ///
+    /// Image tags and git refs often differ by a prefix (e.g. image `1.2.3` for git tag `v1.2.3`)
+    /// so variants of the reference are tried as tags and then branches.
+    pub async fn commit(
         &self,
         owner: &str,
         repo: &str,
         reference: &str,
-        user: &str,
-        token: &str,
-        branch: &str,
-    ) -> Result<String> {
-        let token_url = Self::ghcr_token_url(owner, repo);
+    ) -> Result<Option<(String, RevisionKind)>> {
+        for name in Self::ref_variants(reference) {
+            for (kind, revision_kind) in REF_KINDS {
+                let response = self
+                    .client
+                    .get(self.ref_url(owner, repo, kind, &name))
+                    .header("User-Agent", "Rust Binary")
+                    .send()
+                    .await?;
+
+                if response.status() == StatusCode::NOT_FOUND {
+                    continue;
+                }
+
+                let git_ref = response.error_for_status()?.json::<GitRef>().await?;
+
+                let commit = self.peel(owner, repo, git_ref.object).await?;
+                return Ok(Some((commit, revision_kind)));
+            }
+        }
 
-        let reg_token = self
-            .client
-            .get(&token_url)
-            .basic_auth(user, Some(token))
-            .send()
-            .await?
-            .json::<TokenResponse>()
-            .await?;
+        Ok(None)
+    }
 
-        let manifest_response = self
-            .client
-            .get(Self::ghcr_manifest_url(owner, repo, reference))
-            .header(
-                header::ACCEPT,
-                "application/vnd.docker.distribution.manifest.list.v2+json, \
-                 application/vnd.docker.distribution.manifest.v2+json",
-            )
-            .bearer_auth(&reg_token.token)
-            .send()
-            .await?;
+    /// Dereference annotated tags until reaching the commit
+    async fn peel(&self, owner: &str, repo: &str, mut object: GitObject) -> Result<String> {
+        for _ in 0..MAX_TAG_DEPTH {
+            match object.kind.as_str() {
+                "commit" => return Ok(object.sha),
+                "tag" => {
+                    object = self
+                        .client
+                        .get(self.tag_url(owner, repo, &object.sha))
+                        .header("User-Agent", "Rust Binary")
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .json::<GitRef>()
+                        .await?
+                        .object;
+                }
+                kind => bail!("Reference points at a {kind} instead of a commit"),
+            }
+        }
+
+        bail!("Annotated tag chain is too deep")
+    }
+
+    /// Names a git ref may have for an image reference, in the order they are tried
+    pub fn ref_variants(reference: &str) -> Vec<String> {
+        let mut variants = vec![reference.to_string()];
 
-        if manifest_response.status() == StatusCode::NOT_FOUND {
-            // Manifest is not in GHCR but the file may still be in the repo.
-            // Since Docker Hub does not actually show you the file directly we want to try the
-            // reference on the repo first and then fallback to Docker Hub.
-            return Ok(reference.to_string());
+        let versioned = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+        match reference.strip_prefix('v') {
+            Some(version) if versioned(version) => variants.push(version.to_string()),
+            _ if versioned(reference) => variants.push(format!("v{reference}")),
+            _ => {}
         }
 
-        let content_type = manifest_response
-            .headers()
-            .get(header::CONTENT_TYPE)
-            .and_then(|content_type| content_type.to_str().ok())
-            .unwrap_or_default();
-
-        let manifest: Manifest = if content_type
-            .starts_with("application/vnd.docker.distribution.manifest.list.v2+json")
-        {
-            let digest = &manifest_response
-                .json::<ManifestList>()
-                .await?
-                .manifests
-                .first()
-                .expect("Empty manifest list")
-                .digest
-                .clone();
-
-            self.client
-                .get(Self::ghcr_manifest_url(owner, repo, digest))
-                .header(
-                    header::ACCEPT,
-                    "application/vnd.docker.distribution.manifest.v2+json",
-                )
-                .bearer_auth(&reg_token.token)
-                .send()
-                .await?
-                .json::<Manifest>()
-                .await?
-        } else {
-            manifest_response.json().await?
-        };
-
-        let image_config: ImageConfig = self
-            .client
-            .get(Self::ghcr_blob_by_digest_url(
-                owner,
-                repo,
-                &manifest.config.digest,
-            ))
-            .bearer_auth(&reg_token.token)
-            .send()
-            .await?
-            .json::<ImageConfig>()
-            .await?;
+        match reference.strip_prefix("release-") {
+            Some(release) => variants.push(release.to_string()),
+            None => variants.push(format!("release-{reference}")),
+        }
 
-        let rev = image_config
-            .config
-            .labels
-            .and_then(|labels| {
-                labels
-                    .get(DEFAULT_REVISION)
-                    .or_else(|| labels.get(OLD_REVISION))
-                    .cloned()
-            })
-            .filter(|s| !s.is_empty())
-            .unwrap_or_else(|| branch.to_string());
-
-        Ok(rev)
+        variants
     }
-}
 
-#[derive(Deserialize, Debug)]
-struct TokenResponse {
-    token: String,
-}
+    fn repo_url(&self, owner: &str, repo: &str) -> String {
+        format!("{}/repos/{owner}/{repo}", self.api)
+    }
 
-#[derive(Deserialize)]
-struct ManifestList {
-    manifests: Vec<PlatformManifest>,
-}
+    fn file_url(&self, owner: &str, repo: &str, path: &str, ref_: &str) -> String {
+        format!(
+            "{}/repos/{owner}/{repo}/contents/{path}?ref={ref_}",
+            self.api
+        )
+    }
 
-#[derive(Deserialize)]
-struct PlatformManifest {
-    digest: String,
-}
+    fn raw_url(&self, owner: &str, repo: &str, path: &str, revision: &str) -> String {
+        format!("{}/{owner}/{repo}/{revision}/{path}", self.raw)
+    }
 
-#[derive(Deserialize)]
-struct Manifest {
-    config: Descriptor,
-}
+    fn tree_url(&self, owner: &str, repo: &str, revision: &str) -> String {
+        format!(
+            "{}/repos/{owner}/{repo}/git/trees/{revision}?recursive=1",
+            self.api
+        )
+    }
 
-#[derive(Deserialize)]
-struct Descriptor {
-    digest: String,
-}
+    fn ref_url(&self, owner: &str, repo: &str, kind: &str, name: &str) -> String {
+        format!("{}/repos/{owner}/{repo}/git/ref/{kind}/{name}", self.api)
+    }
 
-#[derive(Deserialize, Debug)]
-struct ImageConfig {
-    config: ConfigSection,
-}
+    fn tag_url(&self, owner: &str, repo: &str, sha: &str) -> String {
+        format!("{}/repos/{owner}/{repo}/git/tags/{sha}", self.api)
+    }
+
+    /// Find the revision of a GHCR image from its labels, if the image has one
+    pub async fn revision(
+        &self,
+        registry: &Registry,
+        owner: &str,
+        repo: &str,
+        reference: &str,
+    ) -> Result<Option<String>> {
+        let labels = registry
+            .labels(&format!("{owner}/{repo}"), reference)
+            .await?;
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct ConfigSection {
-    labels: Option<HashMap<String, String>>,
+        Ok(labels.and_then(|labels| {
+            labels
+                .get(DEFAULT_REVISION)
+                .or_else(|| labels.get(OLD_REVISION))
+                .filter(|revision| !revision.is_empty())
+                .cloned()
+        }))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::Parser;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path, query_param},
+    };
 
     const OWNER: &str = "owner";
     const REPO: &str = "repo";
@@ -313,6 +524,103 @@ mod tests {
             assert!(error.is_err());
         }
 
+        #[test]
+        fn test_ref_variants() {
+            assert_eq!(
+                Github::ref_variants("1.2.3"),
+                vec!["1.2.3", "v1.2.3", "release-1.2.3"]
+            );
+        }
+
+        #[test]
+        fn test_ref_variants_with_prefix() {
+            assert_eq!(
+                Github::ref_variants("v1.2.3"),
+                vec!["v1.2.3", "1.2.3", "release-v1.2.3"]
+            );
+            assert_eq!(
+                Github::ref_variants("release-1.2.3"),
+                vec!["release-1.2.3", "1.2.3"]
+            );
+        }
+
+        #[test]
+        fn test_ref_variants_branch() {
+            assert_eq!(
+                Github::ref_variants("nightly"),
+                vec!["nightly", "release-nightly"]
+            );
+        }
+
+        #[tokio::test]
+        async fn test_tree_truncated() -> Result<()> {
+            let server = MockServer::start().await;
+
+            Mock::given(method("GET"))
+                .and(path(format!("/repos/{OWNER}/{REPO}/git/trees/{REF}")))
+                .and(query_param("recursive", "1"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "tree": [
+                        { "path": "services", "type": "tree" },
+                        { "path": "services/Dockerfile", "type": "blob" }
+                    ],
+                    "truncated": true
+                })))
+                .mount(&server)
+                .await;
+
+            let tree = Github::new()
+                .with_api(&server.uri())
+                .tree(OWNER, REPO, REF)
+                .await?;
+
+            assert_eq!(tree.paths, vec!["services/Dockerfile"]);
+            assert!(tree.truncated);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_bakes_override() -> Result<()> {
+            let server = MockServer::start().await;
+
+            Mock::given(method("GET"))
+                .and(path(format!("/{OWNER}/{REPO}/{REF}/docker-bake.hcl")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(
+                    "target \"app\" {\n  dockerfile = \"app.Dockerfile\"\n  tags = [\"owner/app\"]\n}\n",
+                ))
+                .mount(&server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/{OWNER}/{REPO}/{REF}/docker-bake.override.hcl"
+                )))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_string("target \"app\" {\n  tags = [\"ghcr.io/owner/app\"]\n}\n"),
+                )
+                .mount(&server)
+                .await;
+
+            // The override is listed first in the tree but read after the bake file
+            let tree = ["docker-bake.override.hcl", "docker-bake.hcl"].map(String::from);
+            let bakes = Github::new()
+                .with_api(&server.uri())
+                .bakes(OWNER, REPO, REF, &tree, &Trace::default())
+                .await;
+
+            assert_eq!(bakes.len(), 1);
+            assert_eq!(
+                bakes[0]
+                    .1
+                    .dockerfile(&Parser::parse_image("ghcr.io/owner/app:1.0")?),
+                Some("app.Dockerfile".to_string())
+            );
+
+            Ok(())
+        }
+
         #[test]
         fn test_web_url() {
             assert_eq!(
@@ -328,7 +636,7 @@ mod tests {
         #[test]
         fn test_repo_url() {
             assert_eq!(
-                Github::repo_url(OWNER, REPO),
+                Github::new().repo_url(OWNER, REPO),
                 "https://api.github.com/repos/owner/repo"
             );
         }
@@ -336,33 +644,40 @@ mod tests {
         #[test]
         fn test_file_url() {
             assert_eq!(
-                Github::file_url(OWNER, REPO, PATH, REF),
+                Github::new().file_url(OWNER, REPO, PATH, REF),
                 "https://api.github.com/repos/owner/repo/contents/path?ref=ref"
             );
         }
 
         #[test]
-        fn test_ghcr_token_url() {
+        fn test_raw_url() {
+            assert_eq!(
+                Github::new().raw_url(OWNER, REPO, PATH, REF),
+                "https://raw.githubusercontent.com/owner/repo/ref/path"
+            );
+        }
+
+        #[test]
+        fn test_tree_url() {
             assert_eq!(
-                Github::ghcr_token_url(OWNER, REPO),
-                "https://ghcr.io/token?service=ghcr.io&scope=repository:owner/repo:pull"
+                Github::new().tree_url(OWNER, REPO, REF),
+                "https://api.github.com/repos/owner/repo/git/trees/ref?recursive=1"
             );
         }
 
         #[test]
-        fn test_ghcr_manifest_url() {
+        fn test_ref_url() {
             assert_eq!(
-                Github::ghcr_manifest_url(OWNER, REPO, REF),
-                "https://ghcr.io/v2/owner/repo/manifests/ref"
+                Github::new().ref_url(OWNER, REPO, "tags", REF),
+                "https://api.github.com/repos/owner/repo/git/ref/tags/ref"
             );
         }
 
         #[test]
-        fn test_ghcr_blob_by_digest_url() {
-            let digest = "aec5512345678901234567890123456789012345678901234567890123456789";
+        fn test_tag_url() {
             assert_eq!(
-                Github::ghcr_blob_by_digest_url(OWNER, REPO, digest),
-                format!("https://ghcr.io/v2/{OWNER}/{REPO}/blobs/{digest}")
+                Github::new().tag_url(OWNER, REPO, "sha"),
+                "https://api.github.com/repos/owner/repo/git/tags/sha"
             );
         }
     }
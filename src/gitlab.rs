@@ -0,0 +1,229 @@
+use anyhow::{Result, anyhow};
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+
+/// Host of the GitLab.com container registry
+pub const REGISTRY: &str = "registry.gitlab.com";
+
+/// Minimum number of components of a project path (namespace and project)
+const MIN_PROJECT_DEPTH: usize = 2;
+
+pub struct Gitlab {
+    client: Client,
+}
+
+impl Gitlab {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+        }
+    }
+
+    /// Candidate project paths for a registry path, longest first
+    ///
+    /// Images live at the project path optionally followed by image name components
+    /// (e.g. "group/subgroup/project/image") so trailing components are dropped one at a time.
+    pub fn projects(path: &[&str]) -> Vec<String> {
+        (MIN_PROJECT_DEPTH..=path.len())
+            .rev()
+            .map(|depth| path[..depth].join("/"))
+            .collect()
+    }
+
+    /// Check if a project exists and return the default branch, `None` if it does not exist
+    pub async fn check_project(&self, project: &str) -> Result<Option<String>> {
+        let url = Self::project_url(project);
+
+        let response = self.client.get(&url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let json = response.error_for_status()?.json::<Value>().await?;
+        let branch = json["default_branch"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Project {project} has no default branch"))?;
+
+        Ok(Some(branch.to_string()))
+    }
+
+    /// Check if a file exists at a given path and revision
+    pub async fn file_exists(&self, project: &str, path: &str, ref_: &str) -> Result<bool> {
+        let url = Self::file_url(project, path, ref_);
+
+        let response = self.client.head(&url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        response.error_for_status()?;
+        Ok(true)
+    }
+
+    /// Download the contents of a file at a revision
+    pub async fn raw(&self, project: &str, path: &str, revision: &str) -> Result<String> {
+        let contents = self
+            .client
+            .get(Self::raw_url(project, path, revision))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(contents)
+    }
+
+    /// Extract the project path from the URL of its page
+    pub fn split_source(source: &str) -> Option<&str> {
+        source.strip_prefix("https://gitlab.com/")
+    }
+
+    /// Format a GitLab URL for a file at a given revision
+    pub fn web_url(project: &str, file_path: &str, revision: &str) -> String {
+        format!("https://gitlab.com/{project}/-/blob/{revision}/{file_path}")
+    }
+
+    /// Format a GitLab URL for the project page
+    pub fn web_project_url(project: &str) -> String {
+        format!("https://gitlab.com/{project}")
+    }
+
+    fn project_url(project: &str) -> String {
+        format!(
+            "https://gitlab.com/api/v4/projects/{}",
+            Self::encode(project)
+        )
+    }
+
+    fn file_url(project: &str, path: &str, ref_: &str) -> String {
+        format!(
+            "https://gitlab.com/api/v4/projects/{}/repository/files/{}?ref={}",
+            Self::encode(project),
+            Self::encode(path),
+            Self::encode(ref_)
+        )
+    }
+
+    fn raw_url(project: &str, path: &str, revision: &str) -> String {
+        format!(
+            "https://gitlab.com/api/v4/projects/{}/repository/files/{}/raw?ref={}",
+            Self::encode(project),
+            Self::encode(path),
+            Self::encode(revision)
+        )
+    }
+
+    /// The API addresses projects, files and refs by their URL encoded path
+    fn encode(path: &str) -> String {
+        path.bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{byte:02X}"),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROJECT: &str = "group/subgroup/project";
+    const PATH: &str = "docker/Dockerfile";
+    const REF: &str = "ref";
+
+    mod public {
+        use super::*;
+
+        #[test]
+        fn test_projects() {
+            assert_eq!(
+                Gitlab::projects(&["group", "subgroup", "project", "image"]),
+                vec![
+                    "group/subgroup/project/image",
+                    "group/subgroup/project",
+                    "group/subgroup"
+                ]
+            );
+        }
+
+        #[test]
+        fn test_projects_namespace_and_project() {
+            assert_eq!(
+                Gitlab::projects(&["group", "project"]),
+                vec!["group/project"]
+            );
+        }
+
+        #[test]
+        fn test_projects_too_short() {
+            assert!(Gitlab::projects(&["project"]).is_empty());
+        }
+
+        #[test]
+        fn test_web_url() {
+            assert_eq!(
+                Gitlab::web_url(PROJECT, PATH, REF),
+                "https://gitlab.com/group/subgroup/project/-/blob/ref/docker/Dockerfile"
+            );
+        }
+
+        #[test]
+        fn test_web_project_url() {
+            assert_eq!(
+                Gitlab::web_project_url(PROJECT),
+                "https://gitlab.com/group/subgroup/project"
+            );
+        }
+
+        #[test]
+        fn test_split_source() {
+            assert_eq!(
+                Gitlab::split_source(&Gitlab::web_project_url(PROJECT)),
+                Some(PROJECT)
+            );
+            assert_eq!(Gitlab::split_source("https://github.com/owner/repo"), None);
+        }
+    }
+
+    mod private {
+        use super::*;
+
+        #[test]
+        fn test_project_url() {
+            assert_eq!(
+                Gitlab::project_url(PROJECT),
+                "https://gitlab.com/api/v4/projects/group%2Fsubgroup%2Fproject"
+            );
+        }
+
+        #[test]
+        fn test_file_url() {
+            assert_eq!(
+                Gitlab::file_url(PROJECT, PATH, REF),
+                "https://gitlab.com/api/v4/projects/group%2Fsubgroup%2Fproject/repository/files/docker%2FDockerfile?ref=ref"
+            );
+        }
+
+        #[test]
+        fn test_raw_url() {
+            assert_eq!(
+                Gitlab::raw_url(PROJECT, PATH, REF),
+                "https://gitlab.com/api/v4/projects/group%2Fsubgroup%2Fproject/repository/files/docker%2FDockerfile/raw?ref=ref"
+            );
+        }
+
+        #[test]
+        fn test_encode() {
+            assert_eq!(Gitlab::encode("release/1.2"), "release%2F1.2");
+            assert_eq!(Gitlab::encode("fix#12 & more"), "fix%2312%20%26%20more");
+            assert_eq!(
+                Gitlab::raw_url(PROJECT, "docker/app #1.Dockerfile", "release/1.2"),
+                "https://gitlab.com/api/v4/projects/group%2Fsubgroup%2Fproject/repository/files/docker%2Fapp%20%231.Dockerfile/raw?ref=release%2F1.2"
+            );
+        }
+    }
+}
@@ -0,0 +1,147 @@
+/// Instructions of the Dockerfile reference
+const INSTRUCTIONS: [&str; 18] = [
+    "ADD",
+    "ARG",
+    "CMD",
+    "COPY",
+    "ENTRYPOINT",
+    "ENV",
+    "EXPOSE",
+    "FROM",
+    "HEALTHCHECK",
+    "LABEL",
+    "MAINTAINER",
+    "ONBUILD",
+    "RUN",
+    "SHELL",
+    "STOPSIGNAL",
+    "USER",
+    "VOLUME",
+    "WORKDIR",
+];
+
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const GREY: &str = "\x1b[90m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Colour the instructions, comments and variables of a Dockerfile with ANSI escape codes
+pub fn dockerfile(contents: &str) -> String {
+    let mut highlighted = String::with_capacity(contents.len());
+    let mut continued = false;
+
+    for line in contents.split_inclusive('\n') {
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        let trimmed = text.trim_start();
+        let indent = &text[..text.len() - trimmed.len()];
+
+        if trimmed.starts_with('#') {
+            // Comments within a continued instruction do not end it
+            highlighted.push_str(&format!("{indent}{GREY}{trimmed}{RESET}{newline}"));
+            continue;
+        }
+
+        highlighted.push_str(indent);
+        let rest = match trimmed.split_once(char::is_whitespace) {
+            _ if continued => trimmed,
+            Some((word, _)) if instruction(word) => {
+                highlighted.push_str(&format!("{BOLD_BLUE}{word}{RESET}"));
+                &trimmed[word.len()..]
+            }
+            None if instruction(trimmed) => {
+                highlighted.push_str(&format!("{BOLD_BLUE}{trimmed}{RESET}"));
+                ""
+            }
+            _ => trimmed,
+        };
+        highlighted.push_str(&variables(rest));
+        highlighted.push_str(newline);
+
+        continued = text.trim_end().ends_with('\\');
+    }
+
+    highlighted
+}
+
+fn instruction(word: &str) -> bool {
+    INSTRUCTIONS
+        .iter()
+        .any(|instruction| instruction.eq_ignore_ascii_case(word))
+}
+
+/// Colour `$NAME` and `${NAME...}` references
+fn variables(text: &str) -> String {
+    let mut highlighted = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        highlighted.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let length = if after.starts_with('{') {
+            after.find('}').map(|end| end + 1)
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (end > 0).then_some(end)
+        };
+
+        match length {
+            Some(length) => {
+                highlighted.push_str(&format!("{YELLOW}${}{RESET}", &after[..length]));
+                rest = &after[length..];
+            }
+            None => {
+                highlighted.push('$');
+                rest = after;
+            }
+        }
+    }
+    highlighted.push_str(rest);
+
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dockerfile() {
+        let contents = "# syntax=docker/dockerfile:1\nFROM rust:${VERSION} AS build\nrun cargo build \\\n    --release\n";
+
+        assert_eq!(
+            dockerfile(contents),
+            format!(
+                "{GREY}# syntax=docker/dockerfile:1{RESET}\n\
+                 {BOLD_BLUE}FROM{RESET} rust:{YELLOW}${{VERSION}}{RESET} AS build\n\
+                 {BOLD_BLUE}run{RESET} cargo build \\\n    --release\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_dockerfile_continuation() {
+        // A continued shell command is not an instruction even if it looks like one
+        let contents = "RUN apt-get update && \\\n    user add app\nUSER app";
+
+        assert_eq!(
+            dockerfile(contents),
+            format!(
+                "{BOLD_BLUE}RUN{RESET} apt-get update && \\\n    user add app\n{BOLD_BLUE}USER{RESET} app"
+            )
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(
+            variables("$HOME/bin:$PATH costs $5 or $"),
+            format!("{YELLOW}$HOME{RESET}/bin:{YELLOW}$PATH{RESET} costs {YELLOW}$5{RESET} or $")
+        );
+    }
+}
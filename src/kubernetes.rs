@@ -0,0 +1,240 @@
+use crate::yaml;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+/// Path which reads the manifests from stdin (e.g. `helm template ... | dis --k8s -`)
+pub const STDIN: &str = "-";
+
+/// Fields of a pod spec holding containers
+const CONTAINER_FIELDS: [&str; 3] = ["initContainers", "containers", "ephemeralContainers"];
+
+pub struct Kubernetes;
+
+/// A container of a workload with the image it runs
+#[derive(Debug, PartialEq)]
+pub struct Container {
+    /// Workload and container (e.g. "deployment/web/app")
+    pub name: String,
+    pub image: String,
+}
+
+impl Kubernetes {
+    /// Read the containers of the manifests in a file, the YAML files of a directory or stdin
+    pub fn read(path: &Path) -> Result<Vec<Container>> {
+        if path == Path::new(STDIN) {
+            let mut manifests = String::new();
+            io::stdin().read_to_string(&mut manifests)?;
+            return Self::containers(&manifests).context("Failed to parse the manifests of stdin");
+        }
+
+        let mut containers = vec![];
+        for file in yaml::files(path, true)? {
+            let manifests = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            containers.extend(
+                Self::containers(&manifests)
+                    .with_context(|| format!("Failed to parse {}", file.display()))?,
+            );
+        }
+
+        Ok(containers)
+    }
+
+    /// Parse the containers of every workload in multi-document YAML
+    pub fn containers(manifests: &str) -> Result<Vec<Container>> {
+        let mut containers = vec![];
+
+        for document in serde_yaml::Deserializer::from_str(manifests) {
+            let manifest = Value::deserialize(document)?;
+            Self::manifest_containers(&manifest, &mut containers);
+        }
+
+        Ok(containers)
+    }
+
+    fn manifest_containers(manifest: &Value, containers: &mut Vec<Container>) {
+        let Some(kind) = manifest.get("kind").and_then(Value::as_str) else {
+            return;
+        };
+
+        // `kubectl get -o yaml` wraps the manifests in a list
+        if kind == "List" {
+            for item in manifest
+                .get("items")
+                .and_then(Value::as_sequence)
+                .into_iter()
+                .flatten()
+            {
+                Self::manifest_containers(item, containers);
+            }
+            return;
+        }
+
+        let spec = manifest.get("spec");
+        let pod = match kind {
+            "Pod" => spec,
+            "Deployment"
+            | "StatefulSet"
+            | "DaemonSet"
+            | "ReplicaSet"
+            | "ReplicationController"
+            | "Job" => spec.and_then(|spec| spec.get("template")?.get("spec")),
+            "CronJob" => spec.and_then(|spec| {
+                spec.get("jobTemplate")?
+                    .get("spec")?
+                    .get("template")?
+                    .get("spec")
+            }),
+            _ => None,
+        };
+        let Some(pod) = pod else {
+            return;
+        };
+
+        let workload = manifest
+            .get("metadata")
+            .and_then(|metadata| metadata.get("name"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+
+        for field in CONTAINER_FIELDS {
+            for container in pod
+                .get(field)
+                .and_then(Value::as_sequence)
+                .into_iter()
+                .flatten()
+            {
+                let Some(image) = container.get("image").and_then(Value::as_str) else {
+                    continue;
+                };
+                let name = container
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+
+                containers.push(Container {
+                    name: format!("{}/{workload}/{name}", kind.to_ascii_lowercase()),
+                    image: image.to_string(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFESTS: &str = r#"
+# Source: chart/templates/deployment.yaml
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web
+spec:
+  template:
+    spec:
+      initContainers:
+        - name: migrate
+          image: ghcr.io/owner/migrate:1.0
+      containers:
+        - name: app
+          image: ghcr.io/owner/web:1.0
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+spec:
+  ports:
+    - port: 80
+---
+---
+apiVersion: batch/v1
+kind: CronJob
+metadata:
+  name: backup
+spec:
+  jobTemplate:
+    spec:
+      template:
+        spec:
+          containers:
+            - name: backup
+              image: postgres:16
+"#;
+
+    fn container(name: &str, image: &str) -> Container {
+        Container {
+            name: name.to_string(),
+            image: image.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_containers() -> Result<()> {
+        assert_eq!(
+            Kubernetes::containers(MANIFESTS)?,
+            vec![
+                container("deployment/web/migrate", "ghcr.io/owner/migrate:1.0"),
+                container("deployment/web/app", "ghcr.io/owner/web:1.0"),
+                container("cronjob/backup/backup", "postgres:16"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_containers_list() -> Result<()> {
+        let manifests = r#"
+apiVersion: v1
+kind: List
+items:
+  - apiVersion: v1
+    kind: Pod
+    metadata:
+      name: debug
+    spec:
+      containers:
+        - name: app
+          image: redis
+      ephemeralContainers:
+        - name: shell
+          image: busybox
+"#;
+
+        assert_eq!(
+            Kubernetes::containers(manifests)?,
+            vec![
+                container("pod/debug/app", "redis"),
+                container("pod/debug/shell", "busybox"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_directory() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        fs::create_dir(directory.path().join("jobs"))?;
+        fs::write(directory.path().join("web.yaml"), MANIFESTS)?;
+        fs::write(
+            directory.path().join("jobs/seed.yml"),
+            "kind: Job\nmetadata:\n  name: seed\nspec:\n  template:\n    spec:\n      containers:\n        - name: seed\n          image: owner/seed\n",
+        )?;
+        fs::write(directory.path().join("README.md"), "kind: Pod")?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(directory.path(), directory.path().join("jobs/loop"))?;
+
+        let containers = Kubernetes::read(directory.path())?;
+        assert_eq!(containers.len(), 4);
+        assert_eq!(containers[0], container("job/seed/seed", "owner/seed"));
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+use crate::{
+    github::Github,
+    parser::DEFAULT_TAG,
+    resolution::{Resolution, Revision, RevisionKind, Strategy},
+};
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use std::collections::HashMap;
+
+/// Dockerfile name used by the official images when an entry does not set `File`
+const DEFAULT_FILE: &str = "Dockerfile";
+
+pub struct Library {
+    client: Client,
+}
+
+/// A single block of a docker-library manifest file with the global defaults applied
+#[derive(Debug, Default, PartialEq)]
+pub struct Entry {
+    pub tags: Vec<String>,
+    pub shared_tags: Vec<String>,
+    pub git_repo: Option<String>,
+    pub git_commit: Option<String>,
+    pub directory: Option<String>,
+    pub file: Option<String>,
+}
+
+impl Entry {
+    /// Path to the Dockerfile relative to the root of the source repository
+    pub fn path(&self) -> String {
+        let file = self.file.as_deref().unwrap_or(DEFAULT_FILE);
+
+        match self
+            .directory
+            .as_deref()
+            .map(|directory| directory.trim_matches('/'))
+        {
+            Some("") | Some(".") | None => file.to_string(),
+            Some(directory) => format!("{directory}/{file}"),
+        }
+    }
+
+    fn matches(&self, tag: &str) -> bool {
+        self.tags.iter().chain(&self.shared_tags).any(|t| t == tag)
+    }
+}
+
+impl Library {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+        }
+    }
+
+    /// Resolve an official image to the GitHub URL of the Dockerfile that builds the reference
+    pub async fn url(&self, repo: &str, reference: Option<&str>) -> Result<Resolution> {
+        let manifest = self.manifest(repo).await?;
+        let entries = Self::parse(&manifest);
+        let tag = reference.unwrap_or(DEFAULT_TAG);
+
+        let entry = Self::find(&entries, tag)
+            .ok_or_else(|| anyhow!("Tag {tag} is not listed in the {repo} manifest"))?;
+
+        let (git_repo, git_commit) = entry
+            .git_repo
+            .as_deref()
+            .zip(entry.git_commit.as_deref())
+            .ok_or_else(|| anyhow!("Entry for tag {tag} has no GitRepo or GitCommit"))?;
+
+        let source = Github::format_source(git_repo);
+        let (owner, repo) = Github::split_source(&source)?;
+
+        Ok(Resolution::github(
+            &owner,
+            &repo,
+            &entry.path(),
+            Revision::new(git_commit, RevisionKind::Commit),
+            Strategy::OfficialImages,
+        ))
+    }
+
+    /// Fetch the manifest file of an official image
+    pub async fn manifest(&self, repo: &str) -> Result<String> {
+        let response = self
+            .client
+            .get(Self::manifest_url(repo))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.text().await?)
+    }
+
+    /// Parse the RFC 2822 style manifest format into entries
+    ///
+    /// The first block holds global fields (e.g. GitRepo) which every following block inherits
+    /// unless it overrides them.
+    pub fn parse(manifest: &str) -> Vec<Entry> {
+        let mut blocks = Self::blocks(manifest).into_iter();
+
+        let globals = blocks.next().unwrap_or_default();
+
+        blocks
+            .map(|block| {
+                let field = |key: &str| block.get(key).or_else(|| globals.get(key)).cloned();
+
+                Entry {
+                    tags: field("tags").map(|v| Self::list(&v)).unwrap_or_default(),
+                    shared_tags: field("sharedtags")
+                        .map(|v| Self::list(&v))
+                        .unwrap_or_default(),
+                    git_repo: field("gitrepo"),
+                    git_commit: field("gitcommit"),
+                    directory: field("directory"),
+                    file: field("file"),
+                }
+            })
+            .collect()
+    }
+
+    /// Find the entry which lists the tag in either its tags or shared tags
+    pub fn find<'a>(entries: &'a [Entry], tag: &str) -> Option<&'a Entry> {
+        entries.iter().find(|entry| entry.matches(tag))
+    }
+
+    /// Split the manifest into blocks of lowercase keys and their (possibly multi-line) values
+    fn blocks(manifest: &str) -> Vec<HashMap<String, String>> {
+        let mut blocks = vec![];
+        let mut block: HashMap<String, String> = HashMap::new();
+        let mut last_key: Option<String> = None;
+
+        for line in manifest.lines() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            if line.trim().is_empty() {
+                if !block.is_empty() {
+                    blocks.push(std::mem::take(&mut block));
+                }
+                last_key = None;
+                continue;
+            }
+
+            // Lines starting with whitespace continue the value of the previous field
+            if line.starts_with(char::is_whitespace) {
+                if let Some(value) = last_key.as_ref().and_then(|key| block.get_mut(key)) {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once(':') {
+                let key = key.trim().to_ascii_lowercase();
+                block.insert(key.clone(), value.trim().to_string());
+                last_key = Some(key);
+            }
+        }
+
+        if !block.is_empty() {
+            blocks.push(block);
+        }
+
+        blocks
+    }
+
+    fn list(value: &str) -> Vec<String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    fn manifest_url(repo: &str) -> String {
+        format!(
+            "https://raw.githubusercontent.com/docker-library/official-images/master/library/{repo}"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = "\
+# this file is generated via https://github.com/docker-library/redis/blob/master/generate-stackbrew-library.sh
+
+Maintainers: Tianon Gravi <admwiggin@gmail.com> (@tianon),
+             Joseph Ferguson <yosifkit@gmail.com> (@yosifkit)
+GitRepo: https://github.com/docker-library/redis.git
+
+Tags: 7.2.5, 7.2, 7, 7.2.5-bookworm, latest
+SharedTags: stable
+Architectures: amd64, arm64v8
+GitCommit: 0123456789012345678901234567890123456789
+Directory: 7.2/debian
+
+Tags: 7.2.5-alpine, 7.2-alpine
+Architectures: amd64
+GitRepo: https://github.com/docker-library/redis-alpine.git
+GitCommit: 9876543210987654321098765432109876543210
+Directory: 7.2/alpine
+File: Dockerfile.alpine
+";
+
+    mod public {
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+            let entries = Library::parse(MANIFEST);
+
+            assert_eq!(
+                entries,
+                vec![
+                    Entry {
+                        tags: vec![
+                            "7.2.5".to_string(),
+                            "7.2".to_string(),
+                            "7".to_string(),
+                            "7.2.5-bookworm".to_string(),
+                            "latest".to_string(),
+                        ],
+                        shared_tags: vec!["stable".to_string()],
+                        git_repo: Some("https://github.com/docker-library/redis.git".to_string()),
+                        git_commit: Some("0123456789012345678901234567890123456789".to_string()),
+                        directory: Some("7.2/debian".to_string()),
+                        file: None,
+                    },
+                    Entry {
+                        tags: vec!["7.2.5-alpine".to_string(), "7.2-alpine".to_string()],
+                        shared_tags: vec![],
+                        git_repo: Some(
+                            "https://github.com/docker-library/redis-alpine.git".to_string()
+                        ),
+                        git_commit: Some("9876543210987654321098765432109876543210".to_string()),
+                        directory: Some("7.2/alpine".to_string()),
+                        file: Some("Dockerfile.alpine".to_string()),
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn test_find_tag() {
+            let entries = Library::parse(MANIFEST);
+            let entry = Library::find(&entries, "7.2-alpine").expect("Entry not found");
+
+            assert_eq!(entry.path(), "7.2/alpine/Dockerfile.alpine");
+        }
+
+        #[test]
+        fn test_find_shared_tag() {
+            let entries = Library::parse(MANIFEST);
+            let entry = Library::find(&entries, "stable").expect("Entry not found");
+
+            assert_eq!(entry.path(), "7.2/debian/Dockerfile");
+        }
+
+        #[test]
+        fn test_find_missing_tag() {
+            let entries = Library::parse(MANIFEST);
+            assert!(Library::find(&entries, "6.0").is_none());
+        }
+
+        #[test]
+        fn test_path_root_directory() {
+            let entry = Entry {
+                directory: Some(".".to_string()),
+                ..Default::default()
+            };
+
+            assert_eq!(entry.path(), "Dockerfile");
+        }
+    }
+
+    mod private {
+        use super::*;
+
+        #[test]
+        fn test_manifest_url() {
+            assert_eq!(
+                Library::manifest_url("redis"),
+                "https://raw.githubusercontent.com/docker-library/official-images/master/library/redis"
+            );
+        }
+
+        #[test]
+        fn test_blocks_continuation() {
+            let blocks = Library::blocks(MANIFEST);
+
+            assert_eq!(
+                blocks[0]["maintainers"],
+                "Tianon Gravi <admwiggin@gmail.com> (@tianon), \
+                 Joseph Ferguson <yosifkit@gmail.com> (@yosifkit)"
+            );
+        }
+    }
+}
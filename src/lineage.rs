@@ -0,0 +1,140 @@
+use crate::resolution::Resolution;
+use serde::Serialize;
+use std::fmt;
+
+/// An image and the images its Dockerfile is built from, printed with `--lineage`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Lineage {
+    pub image: String,
+    /// URL of the Dockerfile, or the closest page to it
+    pub url: Option<String>,
+    pub dockerfile: Option<String>,
+    /// Why the base images were not followed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Stop>,
+    pub bases: Vec<Lineage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stop {
+    /// Built from an empty filesystem
+    Scratch,
+    /// Already an image above it in the lineage
+    Cycle,
+    /// Deeper than `--depth`
+    Depth,
+    Error(String),
+}
+
+impl Lineage {
+    pub fn new(image: &str, resolution: &Resolution) -> Self {
+        Self {
+            image: image.to_string(),
+            url: Some(resolution.url.clone()),
+            dockerfile: resolution.dockerfile.clone(),
+            stop: None,
+            bases: vec![],
+        }
+    }
+
+    /// An image which was not resolved
+    pub fn stopped(image: &str, stop: Stop) -> Self {
+        Self {
+            image: image.to_string(),
+            url: None,
+            dockerfile: None,
+            stop: Some(stop),
+            bases: vec![],
+        }
+    }
+
+    fn label(&self) -> String {
+        let mut label = self.image.clone();
+
+        if let Some(url) = &self.url {
+            label.push_str(&format!("  {url}"));
+        }
+
+        match &self.stop {
+            None | Some(Stop::Scratch) => {}
+            Some(stop) => label.push_str(&format!("  ({stop})")),
+        }
+
+        label
+    }
+
+    fn fmt_bases(&self, f: &mut fmt::Formatter<'_>, prefix: &str) -> fmt::Result {
+        for (index, base) in self.bases.iter().enumerate() {
+            let (branch, indent) = if index + 1 == self.bases.len() {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+
+            writeln!(f, "{prefix}{branch}{}", base.label())?;
+            base.fmt_bases(f, &format!("{prefix}{indent}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Lineage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.label())?;
+        self.fmt_bases(f, "")
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Scratch => write!(f, "scratch"),
+            Stop::Cycle => write!(f, "cycle"),
+            Stop::Depth => write!(f, "depth limit"),
+            Stop::Error(error) => write!(f, "error: {error}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolution::Strategy;
+
+    fn lineage(image: &str, bases: Vec<Lineage>) -> Lineage {
+        let resolution = Resolution::new(format!("https://example.com/{image}"), Strategy::Ranking);
+
+        Lineage {
+            bases,
+            ..Lineage::new(image, &resolution)
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let tree = lineage(
+            "app",
+            vec![
+                lineage(
+                    "node",
+                    vec![lineage(
+                        "debian",
+                        vec![Lineage::stopped("scratch", Stop::Scratch)],
+                    )],
+                ),
+                Lineage::stopped("app:base", Stop::Cycle),
+            ],
+        );
+
+        assert_eq!(
+            tree.to_string(),
+            "app  https://example.com/app\n\
+             ├── node  https://example.com/node\n\
+             │   └── debian  https://example.com/debian\n\
+             │       └── scratch\n\
+             └── app:base  (cycle)\n"
+        );
+    }
+}
@@ -1,20 +1,41 @@
+mod archive;
+mod bake;
 mod cli;
+mod compose;
+mod daemon;
+mod discovery;
 mod docker;
+mod dockerfile;
 mod github;
+mod gitlab;
+mod highlight;
+mod kubernetes;
+mod library;
+mod lineage;
 mod parser;
+mod platform;
+mod registry;
+mod resolution;
+mod trace;
+mod variables;
+mod workflow;
+mod yaml;
 
 use anyhow::Result;
 use clap::{CommandFactory, Parser};
 use cli::Cli;
-use std::process::exit;
+use std::{env, process::exit};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Compose files take variables from the shell and their own `.env` file, not this one
+    let environment = env::vars().collect();
     dotenvy::dotenv().ok();
 
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    cli.environment = environment;
 
-    if cli.image.is_empty() {
+    if cli.image.as_deref() == Some("") {
         let _ = Cli::command().print_help();
         exit(1);
     }
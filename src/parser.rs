@@ -1,40 +1,294 @@
+use anyhow::{Result, bail};
+use std::fmt;
+
+/// Registry hosts which all refer to Docker Hub
+const DOCKER_HUB: [&str; 3] = ["docker.io", "index.docker.io", "registry-1.docker.io"];
+
+/// Tag pulled when a reference has neither a tag nor a digest
+pub const DEFAULT_TAG: &str = "latest";
+
+/// Namespace of the Docker Hub official images
+const LIBRARY: &str = "library";
+
+/// Maximum length of the name (domain and path) of a reference
+const NAME_TOTAL_MAX_LENGTH: usize = 255;
+
+/// Maximum length of a tag
+const TAG_MAX_LENGTH: usize = 128;
+
 pub struct Parser;
 
+/// A normalized image reference following the distribution/reference grammar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    /// Registry host with optional port, `None` for Docker Hub
+    pub registry: Option<String>,
+    /// Repository path components (e.g. ["library", "redis"])
+    pub path: Vec<String>,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageReference {
+    /// Borrow the path components for slice pattern matching
+    pub fn path(&self) -> Vec<&str> {
+        self.path.iter().map(String::as_str).collect()
+    }
+
+    /// The reference used to pull the image, the digest takes precedence over the tag
+    pub fn reference(&self) -> Option<&str> {
+        self.digest.as_deref().or(self.tag.as_deref())
+    }
+
+    /// Check if both references name the same repository regardless of tag or digest
+    pub fn same_repository(&self, other: &ImageReference) -> bool {
+        self.registry == other.registry && self.path == other.path
+    }
+
+    /// Registry host of the repository, "docker.io" for Docker Hub
+    pub fn domain(&self) -> &str {
+        self.registry.as_deref().unwrap_or(DOCKER_HUB[0])
+    }
+
+    /// Repository name in its familiar form (e.g. "redis" or "ghcr.io/owner/repo")
+    pub fn name(&self) -> String {
+        match &self.registry {
+            Some(registry) => format!("{registry}/{}", self.path.join("/")),
+            None => match self.path().as_slice() {
+                [LIBRARY, repo] => repo.to_string(),
+                path => path.join("/"),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+
+        if let Some(tag) = &self.tag {
+            write!(f, ":{tag}")?;
+        }
+
+        if let Some(digest) = &self.digest {
+            write!(f, "@{digest}")?;
+        }
+
+        Ok(())
+    }
+}
+
 impl Parser {
-    pub fn parse_image(image: &str) -> (Option<&str>, Vec<&str>, Option<&str>) {
-        let (name, reference) = Self::split_ref(image);
-        let mut parts: Vec<&str> = name.split('/').collect();
+    /// Parse and normalize an image reference
+    ///
+    /// Grammar (distribution/reference):
+    ///
+    /// ```text
+    /// reference  := name [ ":" tag ] [ "@" digest ]
+    /// name       := [domain '/'] path-component ['/' path-component]*
+    /// domain     := host [':' port-number]
+    /// tag        := /[\w][\w.-]{0,127}/
+    /// digest     := algorithm ":" /[0-9a-fA-F]{32,}/
+    /// ```
+    pub fn parse_image(image: &str) -> Result<ImageReference> {
+        if image.is_empty() {
+            bail!("Invalid reference format: reference is empty");
+        }
+
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (image, None),
+        };
+        let (name, tag) = Self::split_tag(name);
+
+        if name.is_empty() {
+            bail!("Invalid reference format: {image} has no repository name");
+        }
+        if name.len() > NAME_TOTAL_MAX_LENGTH {
+            bail!(
+                "Invalid reference format: repository name must not be more than {NAME_TOTAL_MAX_LENGTH} characters"
+            );
+        }
+
+        let (domain, remainder) = Self::split_domain(name);
+
+        if let Some(domain) = domain {
+            Self::validate_domain(domain)?;
+        }
+
+        let mut path = vec![];
+        for component in remainder.split('/') {
+            Self::validate_path_component(component)?;
+            path.push(component.to_string());
+        }
+
+        if let Some(tag) = tag {
+            Self::validate_tag(tag)?;
+        }
+        if let Some(digest) = digest {
+            Self::validate_digest(digest)?;
+        }
 
-        let registry = if parts.len() > 1
-            && (parts[0].contains('.') || parts[0].contains(':') || parts[0].contains("localhost"))
+        let registry = domain.filter(|domain| !DOCKER_HUB.contains(domain));
+
+        if registry.is_none() && path.len() == 1 {
+            path.insert(0, LIBRARY.to_string());
+        }
+
+        Ok(ImageReference {
+            registry: registry.map(String::from),
+            path,
+            tag: tag.map(String::from),
+            digest: digest.map(String::from),
+        })
+    }
+
+    fn split_tag(name: &str) -> (&str, Option<&str>) {
+        if let Some(idx) = name.rfind(':')
+            && !name[idx..].contains('/')
         {
-            let registry = parts.remove(0);
-            if registry == "docker.io" {
-                None
-            } else {
-                Some(registry)
+            return (&name[..idx], Some(&name[idx + 1..]));
+        }
+        (name, None)
+    }
+
+    /// The first component is a domain if it looks like a host (has a '.' or ':', is localhost or
+    /// has uppercase characters which are invalid in a path)
+    fn split_domain(name: &str) -> (Option<&str>, &str) {
+        match name.split_once('/') {
+            Some((first, remainder))
+                if first.contains(['.', ':'])
+                    || first == "localhost"
+                    || first.chars().any(|c| c.is_ascii_uppercase()) =>
+            {
+                (Some(first), remainder)
+            }
+            _ => (None, name),
+        }
+    }
+
+    fn validate_domain(domain: &str) -> Result<()> {
+        let (host, port) = if let Some(rest) = domain.strip_prefix('[') {
+            // IPv6 address (e.g. [::1]:5000)
+            let Some((address, port)) = rest.split_once(']') else {
+                bail!("Invalid reference format: unterminated IPv6 address in {domain}");
+            };
+            if address.is_empty() || !address.chars().all(|c| c.is_ascii_hexdigit() || c == ':') {
+                bail!("Invalid reference format: {address} is not a valid IPv6 address");
+            }
+            match port {
+                "" => (None, None),
+                port => match port.strip_prefix(':') {
+                    Some(port) => (None, Some(port)),
+                    None => bail!("Invalid reference format: invalid domain {domain}"),
+                },
             }
         } else {
-            None
+            match domain.split_once(':') {
+                Some((host, port)) => (Some(host), Some(port)),
+                None => (Some(domain), None),
+            }
         };
 
-        if registry.is_none() && parts.len() == 1 {
-            parts.insert(0, "library");
+        if let Some(host) = host {
+            for component in host.split('.') {
+                let valid = !component.is_empty()
+                    && component
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-')
+                    && !component.starts_with('-')
+                    && !component.ends_with('-');
+
+                if !valid {
+                    bail!("Invalid reference format: {domain} is not a valid registry domain");
+                }
+            }
         }
 
-        (registry, parts, reference)
+        if let Some(port) = port
+            && (port.is_empty() || !port.chars().all(|c| c.is_ascii_digit()))
+        {
+            bail!("Invalid reference format: {port} is not a valid port in {domain}");
+        }
+
+        Ok(())
     }
 
-    fn split_ref(image: &str) -> (&str, Option<&str>) {
-        if let Some(idx) = image.rfind('@') {
-            return (&image[..idx], Some(&image[idx + 1..]));
+    /// path-component := [a-z0-9]+ ( ( [_.] | __ | [-]+ ) [a-z0-9]+ )*
+    fn validate_path_component(component: &str) -> Result<()> {
+        if component.is_empty() {
+            bail!("Invalid reference format: repository path has an empty component");
+        }
+        if component.chars().any(|c| c.is_ascii_uppercase()) {
+            bail!("Invalid reference format: repository name {component} must be lowercase");
         }
-        if let Some(idx) = image.rfind(':') {
-            if image[idx..].find('/').is_none() {
-                return (&image[..idx], Some(&image[idx + 1..]));
+
+        let alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+        let mut chars = component.chars().peekable();
+        let mut leading = true;
+
+        while let Some(c) = chars.next() {
+            if alphanumeric(c) {
+                leading = false;
+                continue;
+            }
+
+            // Collect the whole separator and make sure it is surrounded by alphanumerics
+            let mut separator = c.to_string();
+            while let Some(&next) = chars.peek() {
+                if alphanumeric(next) {
+                    break;
+                }
+                separator.push(next);
+                chars.next();
+            }
+
+            let valid = matches!(separator.as_str(), "_" | "." | "__")
+                || separator.chars().all(|c| c == '-');
+
+            if leading || !valid || chars.peek().is_none() {
+                bail!(
+                    "Invalid reference format: repository name component {component} has an invalid separator"
+                );
             }
         }
-        (image, None)
+
+        Ok(())
+    }
+
+    fn validate_tag(tag: &str) -> Result<()> {
+        let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+        let valid = !tag.is_empty()
+            && tag.len() <= TAG_MAX_LENGTH
+            && tag.starts_with(word)
+            && tag.chars().all(|c| word(c) || c == '.' || c == '-');
+
+        if !valid {
+            bail!("Invalid reference format: {tag} is not a valid tag");
+        }
+
+        Ok(())
+    }
+
+    fn validate_digest(digest: &str) -> Result<()> {
+        let Some((algorithm, hex)) = digest.split_once(':') else {
+            bail!("Invalid reference format: digest {digest} has no algorithm");
+        };
+
+        let valid_algorithm = algorithm.split(['+', '.', '-', '_']).all(|component| {
+            component.starts_with(|c: char| c.is_ascii_alphabetic())
+                && component.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+        if !valid_algorithm {
+            bail!("Invalid reference format: {algorithm} is not a valid digest algorithm");
+        }
+
+        if hex.len() < 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("Invalid reference format: {digest} is not a valid digest");
+        }
+
+        Ok(())
     }
 }
 
@@ -43,11 +297,27 @@ mod tests {
     use super::*;
     use anyhow::Result;
 
+    const DIGEST: &str = "sha256:aec5512345678901234567890123456789012345678901234567890123456789";
+
+    fn reference(
+        registry: Option<&str>,
+        path: &[&str],
+        tag: Option<&str>,
+        digest: Option<&str>,
+    ) -> ImageReference {
+        ImageReference {
+            registry: registry.map(String::from),
+            path: path.iter().map(|s| s.to_string()).collect(),
+            tag: tag.map(String::from),
+            digest: digest.map(String::from),
+        }
+    }
+
     #[test]
     fn test_repo() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("project"),
-            (None, vec!["library", "project"], None)
+            Parser::parse_image("project")?,
+            reference(None, &["library", "project"], None, None)
         );
         Ok(())
     }
@@ -55,8 +325,8 @@ mod tests {
     #[test]
     fn test_repo_with_tag() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("project:latest"),
-            (None, vec!["library", "project"], Some("latest"))
+            Parser::parse_image("project:latest")?,
+            reference(None, &["library", "project"], Some("latest"), None)
         );
         Ok(())
     }
@@ -64,17 +334,29 @@ mod tests {
     #[test]
     fn test_repo_with_hash() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("project@sha256:1234"),
-            (None, vec!["library", "project"], Some("sha256:1234"))
+            Parser::parse_image(&format!("project@{DIGEST}"))?,
+            reference(None, &["library", "project"], None, Some(DIGEST))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_repo_with_tag_and_hash() -> Result<()> {
+        let image = Parser::parse_image(&format!("project:latest@{DIGEST}"))?;
+
+        assert_eq!(
+            image,
+            reference(None, &["library", "project"], Some("latest"), Some(DIGEST))
         );
+        assert_eq!(image.reference(), Some(DIGEST));
         Ok(())
     }
 
     #[test]
     fn test_repo_with_namespace() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("namespace/project"),
-            (None, vec!["namespace", "project"], None)
+            Parser::parse_image("namespace/project")?,
+            reference(None, &["namespace", "project"], None, None)
         );
         Ok(())
     }
@@ -82,8 +364,8 @@ mod tests {
     #[test]
     fn test_repo_with_namespace_with_tag() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("namespace/project:latest"),
-            (None, vec!["namespace", "project"], Some("latest"))
+            Parser::parse_image("namespace/project:latest")?,
+            reference(None, &["namespace", "project"], Some("latest"), None)
         );
         Ok(())
     }
@@ -91,8 +373,8 @@ mod tests {
     #[test]
     fn test_repo_with_namespace_with_hash() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("namespace/project@sha256:1234"),
-            (None, vec!["namespace", "project"], Some("sha256:1234"))
+            Parser::parse_image(&format!("namespace/project@{DIGEST}"))?,
+            reference(None, &["namespace", "project"], None, Some(DIGEST))
         );
         Ok(())
     }
@@ -100,8 +382,8 @@ mod tests {
     #[test]
     fn test_registry_with_namespace() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("registry.io/namespace"),
-            (Some("registry.io"), vec!["namespace"], None)
+            Parser::parse_image("registry.io/namespace")?,
+            reference(Some("registry.io"), &["namespace"], None, None)
         );
         Ok(())
     }
@@ -109,8 +391,8 @@ mod tests {
     #[test]
     fn test_registry_with_namespace_with_repo() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("registry.io/namespace/project"),
-            (Some("registry.io"), vec!["namespace", "project"], None)
+            Parser::parse_image("registry.io/namespace/project")?,
+            reference(Some("registry.io"), &["namespace", "project"], None, None)
         );
         Ok(())
     }
@@ -118,11 +400,12 @@ mod tests {
     #[test]
     fn test_registry_with_namespace_with_repo_with_tag() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("registry.io/namespace/project:latest"),
-            (
+            Parser::parse_image("registry.io/namespace/project:latest")?,
+            reference(
                 Some("registry.io"),
-                vec!["namespace", "project"],
-                Some("latest")
+                &["namespace", "project"],
+                Some("latest"),
+                None
             )
         );
         Ok(())
@@ -131,13 +414,143 @@ mod tests {
     #[test]
     fn test_registry_with_namespace_with_repo_with_hash() -> Result<()> {
         assert_eq!(
-            Parser::parse_image("registry.io/namespace/project@sha256:1234"),
-            (
+            Parser::parse_image(&format!("registry.io/namespace/project@{DIGEST}"))?,
+            reference(
                 Some("registry.io"),
-                vec!["namespace", "project"],
-                Some("sha256:1234")
+                &["namespace", "project"],
+                None,
+                Some(DIGEST)
             )
         );
         Ok(())
     }
+
+    #[test]
+    fn test_registry_with_nested_path() -> Result<()> {
+        assert_eq!(
+            Parser::parse_image("registry.gitlab.com/group/subgroup/project/image:1.0")?,
+            reference(
+                Some("registry.gitlab.com"),
+                &["group", "subgroup", "project", "image"],
+                Some("1.0"),
+                None
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_with_port() -> Result<()> {
+        assert_eq!(
+            Parser::parse_image("localhost:5000/project:1.0")?,
+            reference(Some("localhost:5000"), &["project"], Some("1.0"), None)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_ipv6() -> Result<()> {
+        assert_eq!(
+            Parser::parse_image("[::1]:5000/namespace/project")?,
+            reference(Some("[::1]:5000"), &["namespace", "project"], None, None)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_docker_hub_aliases() -> Result<()> {
+        for registry in DOCKER_HUB {
+            assert_eq!(
+                Parser::parse_image(&format!("{registry}/project"))?,
+                reference(None, &["library", "project"], None, None)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_separators() -> Result<()> {
+        assert_eq!(
+            Parser::parse_image("name-space/pro__ject.v2_x---y")?,
+            reference(None, &["name-space", "pro__ject.v2_x---y"], None, None)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_name() -> Result<()> {
+        assert_eq!(
+            Parser::parse_image("docker.io/library/redis:7")?.name(),
+            "redis"
+        );
+        assert_eq!(
+            Parser::parse_image("ghcr.io/owner/repo")?.name(),
+            "ghcr.io/owner/repo"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_repository() -> Result<()> {
+        let image = Parser::parse_image("ghcr.io/owner/repo:1.0")?;
+
+        assert!(image.same_repository(&Parser::parse_image(&format!(
+            "ghcr.io/owner/repo@{DIGEST}"
+        ))?));
+        assert!(!image.same_repository(&Parser::parse_image("owner/repo:1.0")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_display() -> Result<()> {
+        let image = format!("namespace/project:latest@{DIGEST}");
+        assert_eq!(Parser::parse_image(&image)?.to_string(), image);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_uppercase() {
+        assert!(Parser::parse_image("Namespace/project").is_ok());
+        assert!(Parser::parse_image("namespace/Project").is_err());
+        assert!(Parser::parse_image("Project").is_err());
+    }
+
+    #[test]
+    fn test_invalid_empty() {
+        assert!(Parser::parse_image("").is_err());
+        assert!(Parser::parse_image(":latest").is_err());
+        assert!(Parser::parse_image("namespace//project").is_err());
+    }
+
+    #[test]
+    fn test_invalid_separator() {
+        assert!(Parser::parse_image("-project").is_err());
+        assert!(Parser::parse_image("project-").is_err());
+        assert!(Parser::parse_image("pro...ject").is_err());
+        assert!(Parser::parse_image("pro___ject").is_err());
+    }
+
+    #[test]
+    fn test_invalid_tag() {
+        assert!(Parser::parse_image("project:.latest").is_err());
+        assert!(Parser::parse_image("project:la$test").is_err());
+        assert!(Parser::parse_image(&format!("project:{}", "a".repeat(129))).is_err());
+    }
+
+    #[test]
+    fn test_invalid_digest() {
+        assert!(Parser::parse_image("project@sha256:1234").is_err());
+        assert!(Parser::parse_image("project@1234567890123456789012345678901234").is_err());
+    }
+
+    #[test]
+    fn test_invalid_domain() {
+        assert!(Parser::parse_image("-registry.io/project").is_err());
+        assert!(Parser::parse_image("registry.io:port/project").is_err());
+    }
+
+    #[test]
+    fn test_invalid_name_length() {
+        assert!(Parser::parse_image(&"a".repeat(256)).is_err());
+    }
 }
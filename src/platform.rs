@@ -0,0 +1,185 @@
+use anyhow::{Result, anyhow, bail};
+use serde::Deserialize;
+use std::{fmt, str::FromStr};
+
+/// Target platform of an image (e.g. linux/arm64 or linux/arm/v7)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    /// Platform of the machine running the tool
+    ///
+    /// Docker runs Linux images on every host by default so only the architecture is detected.
+    pub fn host() -> Self {
+        let (architecture, variant) = match std::env::consts::ARCH {
+            "x86_64" => ("amd64", None),
+            "x86" => ("386", None),
+            "aarch64" => ("arm64", None),
+            "arm" => ("arm", Some("v7")),
+            "powerpc64" => ("ppc64le", None),
+            arch => (arch, None),
+        };
+
+        Self {
+            os: "linux".to_string(),
+            architecture: architecture.to_string(),
+            variant: variant.map(String::from),
+        }
+        .normalize()
+    }
+
+    /// Check if a platform of a manifest list entry satisfies this platform
+    ///
+    /// A missing variant accepts any variant of the architecture.
+    pub fn matches(&self, other: &Platform) -> bool {
+        let other = other.clone().normalize();
+
+        self.os == other.os
+            && self.architecture == other.architecture
+            && (self.variant.is_none() || self.variant == other.variant)
+    }
+
+    /// Apply the same aliases as containerd so equivalent platforms compare equal
+    pub fn normalize(mut self) -> Self {
+        self.os = self.os.to_ascii_lowercase();
+        self.architecture = match self.architecture.to_ascii_lowercase().as_str() {
+            "x86_64" | "x86-64" => "amd64".to_string(),
+            "aarch64" => "arm64".to_string(),
+            "i386" => "386".to_string(),
+            arch => arch.to_string(),
+        };
+
+        // arm64 is always v8 and arm defaults to v7
+        self.variant = match (self.architecture.as_str(), self.variant.as_deref()) {
+            ("arm64", Some("v8") | Some("8")) => None,
+            ("arm", None) => Some("v7".to_string()),
+            (_, variant) => variant.map(String::from),
+        };
+
+        self
+    }
+}
+
+impl FromStr for Platform {
+    type Err = anyhow::Error;
+
+    /// Parse os/arch[/variant] (e.g. linux/amd64)
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split('/').collect();
+
+        if parts.iter().any(|part| part.is_empty()) {
+            bail!("Invalid platform {s}, expected os/arch[/variant]");
+        }
+
+        let platform = match parts.as_slice() {
+            [os, architecture] => Self {
+                os: os.to_string(),
+                architecture: architecture.to_string(),
+                variant: None,
+            },
+            [os, architecture, variant] => Self {
+                os: os.to_string(),
+                architecture: architecture.to_string(),
+                variant: Some(variant.to_string()),
+            },
+            _ => return Err(anyhow!("Invalid platform {s}, expected os/arch[/variant]")),
+        };
+
+        Ok(platform.normalize())
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platform(os: &str, architecture: &str, variant: Option<&str>) -> Platform {
+        Platform {
+            os: os.to_string(),
+            architecture: architecture.to_string(),
+            variant: variant.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_from_str() -> Result<()> {
+        assert_eq!(
+            "linux/amd64".parse::<Platform>()?,
+            platform("linux", "amd64", None)
+        );
+        assert_eq!(
+            "linux/arm/v6".parse::<Platform>()?,
+            platform("linux", "arm", Some("v6"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_str_aliases() -> Result<()> {
+        assert_eq!(
+            "linux/aarch64".parse::<Platform>()?,
+            platform("linux", "arm64", None)
+        );
+        assert_eq!(
+            "linux/arm64/v8".parse::<Platform>()?,
+            platform("linux", "arm64", None)
+        );
+        assert_eq!(
+            "linux/arm".parse::<Platform>()?,
+            platform("linux", "arm", Some("v7"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_str_invalid() {
+        assert!("linux".parse::<Platform>().is_err());
+        assert!("linux//v7".parse::<Platform>().is_err());
+        assert!("linux/arm/v7/extra".parse::<Platform>().is_err());
+    }
+
+    #[test]
+    fn test_matches() -> Result<()> {
+        let requested = "linux/arm64".parse::<Platform>()?;
+
+        assert!(requested.matches(&platform("linux", "arm64", Some("v8"))));
+        assert!(!requested.matches(&platform("linux", "amd64", None)));
+        assert!(!requested.matches(&platform("windows", "arm64", None)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_variant() -> Result<()> {
+        let requested = "linux/arm/v6".parse::<Platform>()?;
+
+        assert!(requested.matches(&platform("linux", "arm", Some("v6"))));
+        assert!(!requested.matches(&platform("linux", "arm", Some("v7"))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_display() -> Result<()> {
+        assert_eq!(Platform::host().os, "linux");
+        assert_eq!(
+            "linux/arm/v7".parse::<Platform>()?.to_string(),
+            "linux/arm/v7"
+        );
+        Ok(())
+    }
+}
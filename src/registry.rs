@@ -0,0 +1,853 @@
+use crate::platform::Platform;
+use anyhow::{Result, anyhow, bail};
+use reqwest::{Client, RequestBuilder, StatusCode, header};
+use serde::Deserialize;
+use std::{collections::HashMap, error, fmt};
+
+/// Registry host serving Docker Hub images
+pub const DOCKER_HUB: &str = "registry-1.docker.io";
+
+/// Docker manifest list media type
+pub const DOCKER_MANIFEST_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+
+/// Docker image manifest media type
+pub const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+
+/// OCI image index media type
+pub const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+
+/// OCI image manifest media type
+pub const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// Media types which list a manifest per platform
+const INDEX_MEDIA_TYPES: [&str; 2] = [OCI_INDEX, DOCKER_MANIFEST_LIST];
+
+/// Media types of a single platform image manifest
+const MANIFEST_MEDIA_TYPES: [&str; 2] = [OCI_MANIFEST, DOCKER_MANIFEST];
+
+/// Annotation marking the attestation (SBOM/provenance) entries BuildKit adds to manifest lists
+const REFERENCE_TYPE: &str = "vnd.docker.reference.type";
+
+/// Client for any registry implementing the OCI Distribution API
+pub struct Registry {
+    client: Client,
+    host: String,
+    credentials: Option<(String, String)>,
+    platform: Platform,
+}
+
+/// Authorization negotiated with the registry for a repository
+#[derive(Debug, PartialEq)]
+pub enum Auth {
+    Anonymous,
+    Basic,
+    Bearer(String),
+}
+
+/// Parameters of a `WWW-Authenticate` challenge
+#[derive(Debug, Default, PartialEq)]
+pub struct Challenge {
+    pub scheme: String,
+    pub realm: Option<String>,
+    pub service: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// The manifest list has no image for the requested platform
+#[derive(Debug, PartialEq)]
+pub struct PlatformMismatch {
+    pub platform: Platform,
+    pub available: Vec<String>,
+}
+
+impl fmt::Display for PlatformMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No manifest for platform {} (available: {})",
+            self.platform,
+            self.available.join(", ")
+        )
+    }
+}
+
+impl error::Error for PlatformMismatch {}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    #[serde(alias = "access_token")]
+    token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestList {
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub manifests: Vec<PlatformManifest>,
+    pub annotations: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+pub struct PlatformManifest {
+    pub digest: String,
+    pub platform: Option<Platform>,
+    pub annotations: Option<HashMap<String, String>>,
+}
+
+impl PlatformManifest {
+    /// Attestations are stored as manifests with an unknown platform
+    fn attestation(&self) -> bool {
+        let annotated = self
+            .annotations
+            .as_ref()
+            .is_some_and(|annotations| annotations.contains_key(REFERENCE_TYPE));
+        let unknown = self
+            .platform
+            .as_ref()
+            .is_some_and(|platform| platform.os == "unknown" || platform.architecture == "unknown");
+
+        annotated || unknown
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub config: Descriptor,
+    pub annotations: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+pub struct Descriptor {
+    pub digest: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ImageConfig {
+    #[serde(default)]
+    pub config: ConfigSection,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct ConfigSection {
+    pub labels: Option<HashMap<String, String>>,
+}
+
+impl Registry {
+    pub fn new(host: &str) -> Self {
+        Self {
+            client: Client::new(),
+            host: host.to_string(),
+            credentials: None,
+            platform: Platform::host(),
+        }
+    }
+
+    /// Select this platform from manifest lists instead of the host platform
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Authenticate with a username and password (or token) when the registry asks for it
+    pub fn with_credentials(mut self, user: &str, token: &str) -> Self {
+        self.credentials = Some((user.to_string(), token.to_string()));
+        self
+    }
+
+    /// Fetch the labels of the image config, `None` if the manifest does not exist
+    ///
+    /// Annotations of the manifest (and index) are included for keys the config does not label.
+    pub async fn labels(
+        &self,
+        repository: &str,
+        reference: &str,
+    ) -> Result<Option<HashMap<String, String>>> {
+        let auth = self.authenticate(repository).await?;
+
+        let Some(manifest) = self.manifest(repository, reference, &auth).await? else {
+            return Ok(None);
+        };
+
+        let config = self
+            .config(repository, &manifest.config.digest, &auth)
+            .await?;
+
+        let mut labels = manifest.annotations.unwrap_or_default();
+        labels.extend(config.config.labels.unwrap_or_default());
+
+        Ok(Some(labels))
+    }
+
+    /// Ping the registry and negotiate authorization to pull from the repository
+    pub async fn authenticate(&self, repository: &str) -> Result<Auth> {
+        let response = self.client.get(Self::ping_url(&self.host)).send().await?;
+
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(Auth::Anonymous);
+        }
+
+        let challenge = response
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::parse_challenge)
+            .ok_or_else(|| anyhow!("Registry {} sent no authentication challenge", self.host))?;
+
+        match challenge.scheme.as_str() {
+            "basic" => Ok(Auth::Basic),
+            "bearer" => self.token(&challenge, repository).await.map(Auth::Bearer),
+            scheme => bail!("Unsupported authentication scheme {scheme}"),
+        }
+    }
+
+    /// Fetch the image manifest, following a manifest list to the entry for the platform
+    ///
+    /// Annotations of the manifest list are merged into the manifest annotations.
+    pub async fn manifest(
+        &self,
+        repository: &str,
+        reference: &str,
+        auth: &Auth,
+    ) -> Result<Option<Manifest>> {
+        let response = self
+            .authorize(
+                self.client
+                    .get(Self::manifest_url(&self.host, repository, reference)),
+                auth,
+            )
+            .header(
+                header::ACCEPT,
+                [INDEX_MEDIA_TYPES, MANIFEST_MEDIA_TYPES]
+                    .concat()
+                    .join(", "),
+            )
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let response = response.error_for_status()?;
+
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let body = response.bytes().await?;
+
+        let list = serde_json::from_slice::<ManifestList>(&body)?;
+        if !Self::index(&content_type, list.media_type.as_deref()) {
+            return Ok(Some(serde_json::from_slice(&body)?));
+        }
+
+        let digest = &Self::select(&list.manifests, &self.platform)?.digest;
+
+        let mut manifest = self
+            .authorize(
+                self.client
+                    .get(Self::manifest_url(&self.host, repository, digest)),
+                auth,
+            )
+            .header(header::ACCEPT, MANIFEST_MEDIA_TYPES.join(", "))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Manifest>()
+            .await?;
+
+        let mut annotations = list.annotations.unwrap_or_default();
+        annotations.extend(manifest.annotations.unwrap_or_default());
+        manifest.annotations = Some(annotations);
+
+        Ok(Some(manifest))
+    }
+
+    /// Fetch the image config blob
+    pub async fn config(&self, repository: &str, digest: &str, auth: &Auth) -> Result<ImageConfig> {
+        let config = self
+            .authorize(
+                self.client
+                    .get(Self::blob_url(&self.host, repository, digest)),
+                auth,
+            )
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ImageConfig>()
+            .await?;
+
+        Ok(config)
+    }
+
+    /// Check if a manifest response is an index from its content type or `mediaType` field
+    ///
+    /// The content type takes precedence since `mediaType` is optional in OCI documents.
+    pub fn index(content_type: &str, media_type: Option<&str>) -> bool {
+        let is_index = |media_type: &str| {
+            INDEX_MEDIA_TYPES
+                .iter()
+                .any(|index| media_type.starts_with(index))
+        };
+        let is_manifest = |media_type: &str| {
+            MANIFEST_MEDIA_TYPES
+                .iter()
+                .any(|manifest| media_type.starts_with(manifest))
+        };
+
+        if is_index(content_type) || is_manifest(content_type) {
+            return is_index(content_type);
+        }
+
+        media_type.is_some_and(is_index)
+    }
+
+    /// Select the manifest list entry for a platform, skipping attestations
+    pub fn select<'a>(
+        manifests: &'a [PlatformManifest],
+        platform: &Platform,
+    ) -> Result<&'a PlatformManifest> {
+        let candidates: Vec<&PlatformManifest> = manifests
+            .iter()
+            .filter(|manifest| !manifest.attestation())
+            .collect();
+
+        // Single platform lists do not always describe their platform
+        if let [manifest] = candidates.as_slice()
+            && manifest.platform.is_none()
+        {
+            return Ok(manifest);
+        }
+
+        if let Some(manifest) = candidates.iter().find(|manifest| {
+            manifest
+                .platform
+                .as_ref()
+                .is_some_and(|candidate| platform.matches(candidate))
+        }) {
+            return Ok(manifest);
+        }
+
+        let available: Vec<String> = candidates
+            .iter()
+            .filter_map(|manifest| manifest.platform.as_ref())
+            .map(Platform::to_string)
+            .collect();
+
+        if available.is_empty() {
+            bail!("Manifest list has no platforms");
+        }
+
+        bail!(PlatformMismatch {
+            platform: platform.clone(),
+            available,
+        });
+    }
+
+    /// Parse a `WWW-Authenticate` header (e.g. `Bearer realm="...",service="...",scope="..."`)
+    pub fn parse_challenge(header: &str) -> Option<Challenge> {
+        let (scheme, params) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+
+        let mut challenge = Challenge {
+            scheme: scheme.to_ascii_lowercase(),
+            ..Default::default()
+        };
+
+        for (key, value) in Self::parse_params(params) {
+            match key.to_ascii_lowercase().as_str() {
+                "realm" => challenge.realm = Some(value),
+                "service" => challenge.service = Some(value),
+                "scope" => challenge.scope = Some(value),
+                _ => {}
+            }
+        }
+
+        (!challenge.scheme.is_empty()).then_some(challenge)
+    }
+
+    /// Request a token from the realm of a bearer challenge
+    async fn token(&self, challenge: &Challenge, repository: &str) -> Result<String> {
+        let realm = challenge
+            .realm
+            .as_deref()
+            .ok_or_else(|| anyhow!("Bearer challenge from {} has no realm", self.host))?;
+
+        let mut query = vec![("scope", format!("repository:{repository}:pull"))];
+        if let Some(service) = &challenge.service {
+            query.push(("service", service.clone()));
+        }
+
+        let mut request = self.client.get(realm).query(&query);
+        if let Some((user, token)) = &self.credentials {
+            request = request.basic_auth(user, Some(token));
+        }
+
+        let response = request.send().await?;
+        if self.credentials.is_none() && response.status() == StatusCode::UNAUTHORIZED {
+            bail!(
+                "Registry {} needs credentials to pull {repository}",
+                self.host
+            );
+        }
+
+        let response = response.error_for_status()?.json::<TokenResponse>().await?;
+
+        Ok(response.token)
+    }
+
+    fn authorize(&self, request: RequestBuilder, auth: &Auth) -> RequestBuilder {
+        match (auth, &self.credentials) {
+            (Auth::Bearer(token), _) => request.bearer_auth(token),
+            (Auth::Basic, Some((user, token))) => request.basic_auth(user, Some(token)),
+            _ => request,
+        }
+    }
+
+    /// Split comma separated `key=value` pairs where values may be quoted and contain commas
+    fn parse_params(params: &str) -> Vec<(String, String)> {
+        let mut pairs = vec![];
+        let mut rest = params.trim();
+
+        while let Some((key, remainder)) = rest.split_once('=') {
+            let key = key.trim().trim_start_matches(',').trim().to_string();
+
+            let (value, remainder) = if let Some(quoted) = remainder.strip_prefix('"') {
+                match quoted.split_once('"') {
+                    Some((value, remainder)) => (value, remainder),
+                    None => (quoted, ""),
+                }
+            } else {
+                match remainder.split_once(',') {
+                    Some((value, remainder)) => (value, remainder),
+                    None => (remainder, ""),
+                }
+            };
+
+            pairs.push((key, value.trim().to_string()));
+            rest = remainder.trim_start_matches([',', ' ']);
+        }
+
+        pairs
+    }
+
+    /// Local registries are served over plain HTTP
+    fn scheme(host: &str) -> &'static str {
+        let hostname = host.rsplit_once(':').map_or(host, |(hostname, port)| {
+            if port.chars().all(|c| c.is_ascii_digit()) {
+                hostname
+            } else {
+                host
+            }
+        });
+
+        if hostname == "localhost" || hostname.starts_with("127.") || hostname == "[::1]" {
+            "http"
+        } else {
+            "https"
+        }
+    }
+
+    fn ping_url(host: &str) -> String {
+        format!("{}://{host}/v2/", Self::scheme(host))
+    }
+
+    fn manifest_url(host: &str, repository: &str, reference: &str) -> String {
+        format!(
+            "{}://{host}/v2/{repository}/manifests/{reference}",
+            Self::scheme(host)
+        )
+    }
+
+    fn blob_url(host: &str, repository: &str, digest: &str) -> String {
+        format!(
+            "{}://{host}/v2/{repository}/blobs/{digest}",
+            Self::scheme(host)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path, query_param},
+    };
+
+    const HOST: &str = "ghcr.io";
+    const REPOSITORY: &str = "owner/repo";
+    const REF: &str = "ref";
+    const DIGEST: &str = "sha256:aec5512345678901234567890123456789012345678901234567890123456789";
+
+    fn manifest(platform: Option<&str>, attestation: bool) -> PlatformManifest {
+        PlatformManifest {
+            digest: platform.unwrap_or("none").to_string(),
+            platform: platform.map(|platform| platform.parse().expect("Invalid platform")),
+            annotations: attestation.then(|| {
+                HashMap::from([(
+                    REFERENCE_TYPE.to_string(),
+                    "attestation-manifest".to_string(),
+                )])
+            }),
+        }
+    }
+
+    mod public {
+        use super::*;
+
+        #[test]
+        fn test_select() -> Result<()> {
+            let manifests = vec![
+                manifest(Some("unknown/unknown"), true),
+                manifest(Some("linux/amd64"), false),
+                manifest(Some("linux/arm/v7"), false),
+                manifest(Some("linux/arm64/v8"), false),
+            ];
+
+            assert_eq!(
+                Registry::select(&manifests, &"linux/arm64".parse()?)?.digest,
+                "linux/arm64/v8"
+            );
+            assert_eq!(
+                Registry::select(&manifests, &"linux/arm".parse()?)?.digest,
+                "linux/arm/v7"
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_select_no_match() -> Result<()> {
+            let manifests = vec![
+                manifest(Some("linux/amd64"), false),
+                manifest(Some("unknown/unknown"), true),
+            ];
+
+            let error = Registry::select(&manifests, &"linux/s390x".parse()?)
+                .err()
+                .expect("Selected a manifest for the wrong platform");
+            assert_eq!(
+                error.to_string(),
+                "No manifest for platform linux/s390x (available: linux/amd64)"
+            );
+            assert!(error.downcast_ref::<PlatformMismatch>().is_some());
+            Ok(())
+        }
+
+        #[test]
+        fn test_select_empty() -> Result<()> {
+            assert!(Registry::select(&[], &"linux/amd64".parse()?).is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn test_select_single_without_platform() -> Result<()> {
+            let manifests = vec![manifest(None, false)];
+
+            assert_eq!(
+                Registry::select(&manifests, &"linux/amd64".parse()?)?.digest,
+                "none"
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_index() {
+            assert!(Registry::index(OCI_INDEX, None));
+            assert!(Registry::index(
+                DOCKER_MANIFEST_LIST,
+                Some(DOCKER_MANIFEST_LIST)
+            ));
+            assert!(Registry::index("application/json", Some(OCI_INDEX)));
+            assert!(!Registry::index(OCI_MANIFEST, Some(OCI_INDEX)));
+            assert!(!Registry::index(DOCKER_MANIFEST, None));
+            assert!(!Registry::index("application/json", None));
+        }
+
+        #[test]
+        fn test_parse_challenge() {
+            assert_eq!(
+                Registry::parse_challenge(
+                    r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:owner/repo:pull""#
+                ),
+                Some(Challenge {
+                    scheme: "bearer".to_string(),
+                    realm: Some("https://ghcr.io/token".to_string()),
+                    service: Some("ghcr.io".to_string()),
+                    scope: Some("repository:owner/repo:pull".to_string()),
+                })
+            );
+        }
+
+        #[test]
+        fn test_parse_challenge_quoted_comma() {
+            let challenge = Registry::parse_challenge(
+                r#"Bearer realm="https://auth.docker.io/token", scope="repository:a/b:pull,push""#,
+            )
+            .expect("Invalid challenge");
+
+            assert_eq!(challenge.scope.as_deref(), Some("repository:a/b:pull,push"));
+            assert_eq!(challenge.service, None);
+        }
+
+        #[test]
+        fn test_parse_challenge_basic() {
+            assert_eq!(
+                Registry::parse_challenge(r#"Basic realm="Registry Realm""#),
+                Some(Challenge {
+                    scheme: "basic".to_string(),
+                    realm: Some("Registry Realm".to_string()),
+                    ..Default::default()
+                })
+            );
+        }
+
+        #[tokio::test]
+        async fn test_labels_with_bearer_token() -> Result<()> {
+            let server = MockServer::start().await;
+            let host = server.address().to_string();
+
+            Mock::given(method("GET"))
+                .and(path("/v2/"))
+                .respond_with(ResponseTemplate::new(401).insert_header(
+                    "WWW-Authenticate",
+                    format!(
+                        r#"Bearer realm="{}/token",service="registry""#,
+                        server.uri()
+                    ),
+                ))
+                .mount(&server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path("/token"))
+                .and(query_param("service", "registry"))
+                .and(query_param(
+                    "scope",
+                    format!("repository:{REPOSITORY}:pull"),
+                ))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(serde_json::json!({ "access_token": "secret" })),
+                )
+                .mount(&server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path(format!("/v2/{REPOSITORY}/manifests/{REF}")))
+                .and(header("Authorization", "Bearer secret"))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(
+                    serde_json::json!({ "config": { "digest": DIGEST } }).to_string(),
+                    DOCKER_MANIFEST,
+                ))
+                .mount(&server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path(format!("/v2/{REPOSITORY}/blobs/{DIGEST}")))
+                .and(header("Authorization", "Bearer secret"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "config": { "Labels": { "org.opencontainers.image.revision": "abc" } }
+                })))
+                .mount(&server)
+                .await;
+
+            let labels = Registry::new(&host)
+                .labels(REPOSITORY, REF)
+                .await?
+                .expect("Manifest not found");
+
+            assert_eq!(labels["org.opencontainers.image.revision"], "abc");
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_labels_from_oci_index_annotations() -> Result<()> {
+            let server = MockServer::start().await;
+            let host = server.address().to_string();
+            let manifest_digest =
+                "sha256:bec5512345678901234567890123456789012345678901234567890123456789";
+
+            Mock::given(method("GET"))
+                .and(path("/v2/"))
+                .respond_with(ResponseTemplate::new(200))
+                .mount(&server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path(format!("/v2/{REPOSITORY}/manifests/{REF}")))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_raw(
+                        serde_json::json!({
+                            "schemaVersion": 2,
+                            "mediaType": OCI_INDEX,
+                            "manifests": [
+                                {
+                                    "digest": manifest_digest,
+                                    "platform": { "os": "linux", "architecture": "amd64" }
+                                },
+                                {
+                                    "digest": DIGEST,
+                                    "platform": { "os": "unknown", "architecture": "unknown" },
+                                    "annotations": { REFERENCE_TYPE: "attestation-manifest" }
+                                }
+                            ],
+                            "annotations": {
+                                "org.opencontainers.image.source": "https://github.com/owner/repo",
+                                "org.opencontainers.image.revision": "index"
+                            }
+                        })
+                        .to_string(),
+                        OCI_INDEX,
+                    ),
+                )
+                .mount(&server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/v2/{REPOSITORY}/manifests/{manifest_digest}"
+                )))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_raw(
+                        serde_json::json!({
+                            "schemaVersion": 2,
+                            "mediaType": OCI_MANIFEST,
+                            "config": { "digest": DIGEST },
+                            "annotations": { "org.opencontainers.image.revision": "manifest" }
+                        })
+                        .to_string(),
+                        OCI_MANIFEST,
+                    ),
+                )
+                .mount(&server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path(format!("/v2/{REPOSITORY}/blobs/{DIGEST}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "architecture": "amd64",
+                    "config": {}
+                })))
+                .mount(&server)
+                .await;
+
+            let labels = Registry::new(&host)
+                .with_platform("linux/amd64".parse()?)
+                .labels(REPOSITORY, REF)
+                .await?
+                .expect("Manifest not found");
+
+            assert_eq!(
+                labels["org.opencontainers.image.source"],
+                "https://github.com/owner/repo"
+            );
+            assert_eq!(labels["org.opencontainers.image.revision"], "manifest");
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_labels_without_credentials() -> Result<()> {
+            let server = MockServer::start().await;
+            let host = server.address().to_string();
+
+            Mock::given(method("GET"))
+                .and(path("/v2/"))
+                .respond_with(ResponseTemplate::new(401).insert_header(
+                    "WWW-Authenticate",
+                    format!(r#"Bearer realm="{}/token""#, server.uri()),
+                ))
+                .mount(&server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path("/token"))
+                .respond_with(ResponseTemplate::new(401))
+                .mount(&server)
+                .await;
+
+            let error = Registry::new(&host)
+                .labels(REPOSITORY, REF)
+                .await
+                .expect_err("Anonymous token was refused");
+            assert_eq!(
+                error.to_string(),
+                format!("Registry {host} needs credentials to pull {REPOSITORY}")
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_labels_missing_manifest() -> Result<()> {
+            let server = MockServer::start().await;
+            let host = server.address().to_string();
+
+            Mock::given(method("GET"))
+                .and(path("/v2/"))
+                .respond_with(ResponseTemplate::new(200))
+                .mount(&server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path(format!("/v2/{REPOSITORY}/manifests/{REF}")))
+                .respond_with(ResponseTemplate::new(404))
+                .mount(&server)
+                .await;
+
+            assert!(
+                Registry::new(&host)
+                    .labels(REPOSITORY, REF)
+                    .await?
+                    .is_none()
+            );
+
+            Ok(())
+        }
+    }
+
+    mod private {
+        use super::*;
+
+        #[test]
+        fn test_attestation() {
+            assert!(manifest(Some("unknown/unknown"), true).attestation());
+            assert!(manifest(Some("unknown/unknown"), false).attestation());
+            assert!(!manifest(Some("linux/amd64"), false).attestation());
+        }
+
+        #[test]
+        fn test_scheme() {
+            assert_eq!(Registry::scheme(HOST), "https");
+            assert_eq!(Registry::scheme("localhost:5000"), "http");
+            assert_eq!(Registry::scheme("127.0.0.1:5000"), "http");
+            assert_eq!(Registry::scheme("[::1]:5000"), "http");
+        }
+
+        #[test]
+        fn test_ping_url() {
+            assert_eq!(Registry::ping_url(HOST), "https://ghcr.io/v2/");
+        }
+
+        #[test]
+        fn test_manifest_url() {
+            assert_eq!(
+                Registry::manifest_url(HOST, REPOSITORY, REF),
+                "https://ghcr.io/v2/owner/repo/manifests/ref"
+            );
+        }
+
+        #[test]
+        fn test_blob_url() {
+            assert_eq!(
+                Registry::blob_url(HOST, REPOSITORY, DIGEST),
+                format!("https://ghcr.io/v2/{REPOSITORY}/blobs/{DIGEST}")
+            );
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{github::Github, gitlab::Gitlab, parser::ImageReference};
+use serde::Serialize;
+use std::fmt;
+
+/// Version of the JSON documents, incremented when a field changes incompatibly
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Candidates below this confidence are guesses which the user may want to choose between
+pub const AMBIGUOUS_CONFIDENCE: u8 = 80;
+
+/// A guess is only clear when it beats the next candidate by at least this confidence
+pub const AMBIGUOUS_MARGIN: u8 = 10;
+
+/// How the URL of an image was found
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Source labels of an image saved with `docker save` or in an OCI layout
+    ArchiveLabels,
+    /// Source labels of the image in a local container engine
+    LocalLabels,
+    /// Source labels or annotations of the image in its registry
+    RegistryLabels,
+    /// Docker Hub official images manifest
+    OfficialImages,
+    /// GitHub Actions workflow which pushes the image
+    Workflow,
+    /// Bake target tagged with the image
+    Bake,
+    /// Compose service building the image
+    Compose,
+    /// Best ranked Dockerfile of the repository tree
+    Ranking,
+    /// Conventional Dockerfile path when the tree cannot be listed
+    ConventionalPath,
+    /// Dockerfile of the GitLab project owning the registry path
+    Gitlab,
+    /// GitLab project page when its Dockerfile was not found
+    GitlabProject,
+    /// Docker Hub repository page
+    DockerHub,
+    /// Web page of another registry
+    Registry,
+}
+
+impl Strategy {
+    /// Likelihood from 0 to 100 that a Dockerfile found this way builds the image
+    pub fn confidence(self) -> u8 {
+        match self {
+            Strategy::ArchiveLabels
+            | Strategy::LocalLabels
+            | Strategy::RegistryLabels
+            | Strategy::OfficialImages => 100,
+            Strategy::Workflow => 95,
+            Strategy::Bake => 90,
+            Strategy::Compose => 85,
+            Strategy::Gitlab => 60,
+            Strategy::Ranking | Strategy::ConventionalPath => 50,
+            Strategy::GitlabProject | Strategy::DockerHub | Strategy::Registry => 10,
+        }
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            Strategy::ArchiveLabels => "source labels of the saved image",
+            Strategy::LocalLabels => "source labels of the local image",
+            Strategy::RegistryLabels => "source labels of the image in its registry",
+            Strategy::OfficialImages => "listed in the official images manifest",
+            Strategy::Workflow => "pushed as the image by a GitHub Actions workflow",
+            Strategy::Bake => "built by a bake target tagged with the image",
+            Strategy::Compose => "built by a compose service with the image",
+            Strategy::Ranking => "best matching path in the repository",
+            Strategy::ConventionalPath => "conventional Dockerfile path",
+            Strategy::Gitlab => "Dockerfile of the GitLab project",
+            Strategy::GitlabProject => "GitLab project page",
+            Strategy::DockerHub => "Docker Hub repository page",
+            Strategy::Registry => "registry web page",
+        }
+    }
+}
+
+/// Where a revision came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionKind {
+    /// Commit SHA, usually from the revision label
+    Commit,
+    Tag,
+    Branch,
+    /// Image reference used as a git ref which could not be resolved to a tag or branch
+    Reference,
+    /// Default branch used when nothing better is known
+    DefaultBranch,
+}
+
+/// Git revision the URL points at
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Revision {
+    pub name: String,
+    pub kind: RevisionKind,
+    /// Commit the revision was pinned to, if it was resolved
+    pub commit: Option<String>,
+}
+
+/// The parsed image reference
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reference {
+    pub name: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+/// A Dockerfile which may build the image
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Candidate {
+    pub url: String,
+    pub dockerfile: Option<String>,
+    pub strategy: Strategy,
+    /// Likelihood from 0 to 100 that the candidate builds the image
+    pub confidence: u8,
+    pub reason: String,
+}
+
+/// Result of searching for the Dockerfile of an image
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Resolution {
+    pub version: u32,
+    pub input: String,
+    pub reference: Option<Reference>,
+    pub registry: Option<String>,
+    pub source: Option<String>,
+    pub revision: Option<Revision>,
+    pub dockerfile: Option<String>,
+    pub url: String,
+    pub strategy: Strategy,
+    /// Every Dockerfile found, best first, the first is the resolution itself
+    pub candidates: Vec<Candidate>,
+}
+
+/// Document written instead of a resolution when the search fails
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub version: u32,
+    pub input: String,
+    pub error: String,
+}
+
+impl Revision {
+    pub fn new(name: &str, kind: RevisionKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            commit: Github::sha(name).then(|| name.to_string()),
+        }
+    }
+
+    /// The commit when known, otherwise the name
+    pub fn pinned(&self) -> &str {
+        self.commit.as_deref().unwrap_or(&self.name)
+    }
+}
+
+impl Candidate {
+    pub fn new(url: String, dockerfile: Option<&str>, strategy: Strategy) -> Self {
+        Self {
+            url,
+            dockerfile: dockerfile.map(String::from),
+            strategy,
+            confidence: strategy.confidence(),
+            reason: strategy.reason().to_string(),
+        }
+    }
+
+    /// A Dockerfile ranked by its path, the score maps onto the confidence below exact strategies
+    pub fn ranked(url: String, dockerfile: &str, score: i32) -> Self {
+        Self {
+            confidence: (score + 30).clamp(5, 80) as u8,
+            reason: format!("path scored {score} against the image name"),
+            ..Self::new(url, Some(dockerfile), Strategy::Ranking)
+        }
+    }
+
+    /// Sort candidates by confidence, keeping the first of those with the same URL
+    pub fn rank(mut candidates: Vec<Candidate>) -> Vec<Candidate> {
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.confidence));
+
+        let mut ranked: Vec<Candidate> = vec![];
+        for candidate in candidates {
+            if !ranked.iter().any(|other| other.url == candidate.url) {
+                ranked.push(candidate);
+            }
+        }
+
+        ranked
+    }
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>3}%  {}  ({})",
+            self.confidence, self.url, self.reason
+        )
+    }
+}
+
+impl From<&Resolution> for Candidate {
+    fn from(resolution: &Resolution) -> Self {
+        Self::new(
+            resolution.url.clone(),
+            resolution.dockerfile.as_deref(),
+            resolution.strategy,
+        )
+    }
+}
+
+impl From<&ImageReference> for Reference {
+    fn from(image: &ImageReference) -> Self {
+        Self {
+            name: image.name(),
+            repository: image.path.join("/"),
+            tag: image.tag.clone(),
+            digest: image.digest.clone(),
+        }
+    }
+}
+
+impl Resolution {
+    pub fn new(url: String, strategy: Strategy) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            input: String::new(),
+            reference: None,
+            registry: None,
+            source: None,
+            revision: None,
+            dockerfile: None,
+            url,
+            strategy,
+            candidates: vec![],
+        }
+    }
+
+    /// Dockerfile in a GitHub repository at a revision
+    pub fn github(
+        owner: &str,
+        repo: &str,
+        dockerfile: &str,
+        revision: Revision,
+        strategy: Strategy,
+    ) -> Self {
+        Self {
+            source: Some(format!("https://github.com/{owner}/{repo}")),
+            dockerfile: Some(dockerfile.to_string()),
+            ..Self::new(
+                Github::web_url(owner, repo, dockerfile, revision.pinned()),
+                strategy,
+            )
+        }
+        .with_revision(revision)
+    }
+
+    /// Dockerfile in a GitLab project at a revision
+    pub fn gitlab(project: &str, dockerfile: &str, revision: Revision) -> Self {
+        Self {
+            source: Some(Gitlab::web_project_url(project)),
+            dockerfile: Some(dockerfile.to_string()),
+            ..Self::new(
+                Gitlab::web_url(project, dockerfile, revision.pinned()),
+                Strategy::Gitlab,
+            )
+        }
+        .with_revision(revision)
+    }
+
+    /// Describe the input and the image reference it names
+    pub fn with_input(mut self, input: &str, image: Option<&ImageReference>) -> Self {
+        self.input = input.to_string();
+        self.reference = image.map(Reference::from);
+        self.registry = image.map(|image| image.domain().to_string());
+        self
+    }
+
+    /// Keep every candidate, the first should be the resolution
+    pub fn with_candidates(mut self, candidates: Vec<Candidate>) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// The resolution as the only candidate when no others were found
+    pub fn or_candidate(mut self) -> Self {
+        if self.candidates.is_empty() {
+            self.candidates = vec![Candidate::from(&self)];
+        }
+        self
+    }
+
+    /// Check if the best candidate is a guess which barely beats the next one
+    pub fn ambiguous(&self) -> bool {
+        match self.candidates.as_slice() {
+            [best, next, ..] => {
+                best.confidence < AMBIGUOUS_CONFIDENCE
+                    && best.confidence - next.confidence < AMBIGUOUS_MARGIN
+            }
+            _ => false,
+        }
+    }
+
+    fn with_revision(mut self, revision: Revision) -> Self {
+        self.revision = Some(revision);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use anyhow::Result;
+    use serde_json::json;
+
+    const COMMIT: &str = "2f1c5e0a7b9d4c3e8f6a1b2c3d4e5f6a7b8c9d0e";
+
+    #[test]
+    fn test_revision_pinned() {
+        assert_eq!(Revision::new(COMMIT, RevisionKind::Commit).pinned(), COMMIT);
+        assert_eq!(
+            Revision::new("main", RevisionKind::DefaultBranch).pinned(),
+            "main"
+        );
+    }
+
+    #[test]
+    fn test_schema() -> Result<()> {
+        let revision = Revision {
+            commit: Some(COMMIT.to_string()),
+            ..Revision::new("v1.0", RevisionKind::Tag)
+        };
+        let resolution =
+            Resolution::github("owner", "repo", "Dockerfile", revision, Strategy::Ranking)
+                .or_candidate()
+                .with_input(
+                    "ghcr.io/owner/repo:v1.0",
+                    Some(&Parser::parse_image("ghcr.io/owner/repo:v1.0")?),
+                );
+
+        assert_eq!(
+            serde_json::to_value(&resolution)?,
+            json!({
+                "version": 1,
+                "input": "ghcr.io/owner/repo:v1.0",
+                "reference": {
+                    "name": "ghcr.io/owner/repo",
+                    "repository": "owner/repo",
+                    "tag": "v1.0",
+                    "digest": null
+                },
+                "registry": "ghcr.io",
+                "source": "https://github.com/owner/repo",
+                "revision": {"name": "v1.0", "kind": "tag", "commit": COMMIT},
+                "dockerfile": "Dockerfile",
+                "url": format!("https://github.com/owner/repo/blob/{COMMIT}/Dockerfile"),
+                "strategy": "ranking",
+                "candidates": [{
+                    "url": format!("https://github.com/owner/repo/blob/{COMMIT}/Dockerfile"),
+                    "dockerfile": "Dockerfile",
+                    "strategy": "ranking",
+                    "confidence": 50,
+                    "reason": "best matching path in the repository"
+                }]
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_candidate_rank() {
+        let url = |path: &str| format!("https://github.com/owner/repo/blob/main/{path}");
+
+        let ranked = Candidate::rank(vec![
+            Candidate::ranked(url("Dockerfile"), "Dockerfile", 10),
+            Candidate::ranked(url("tests/Dockerfile"), "tests/Dockerfile", -12),
+            Candidate::new(url("Dockerfile"), Some("Dockerfile"), Strategy::Workflow),
+        ]);
+
+        assert_eq!(
+            ranked
+                .iter()
+                .map(|candidate| (candidate.strategy, candidate.confidence))
+                .collect::<Vec<_>>(),
+            vec![(Strategy::Workflow, 95), (Strategy::Ranking, 18)]
+        );
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        let url = |path: &str| format!("https://github.com/owner/repo/blob/main/{path}");
+        let resolution = |candidates: Vec<Candidate>| Resolution {
+            candidates: Candidate::rank(candidates),
+            ..Resolution::new(url("Dockerfile"), Strategy::Ranking)
+        };
+
+        // A root Dockerfile is clearly better than one of the tests
+        assert!(
+            !resolution(vec![
+                Candidate::ranked(url("Dockerfile"), "Dockerfile", 10),
+                Candidate::ranked(url("test/Dockerfile"), "test/Dockerfile", -12),
+            ])
+            .ambiguous()
+        );
+        assert!(
+            resolution(vec![
+                Candidate::ranked(url("Dockerfile"), "Dockerfile", 10),
+                Candidate::ranked(url("docker/Dockerfile"), "docker/Dockerfile", 11),
+            ])
+            .ambiguous()
+        );
+        assert!(
+            !resolution(vec![
+                Candidate::new(url("Dockerfile"), Some("Dockerfile"), Strategy::Workflow),
+                Candidate::new(
+                    url("app/Dockerfile"),
+                    Some("app/Dockerfile"),
+                    Strategy::Workflow
+                ),
+            ])
+            .ambiguous()
+        );
+    }
+
+    #[test]
+    fn test_docker_hub_registry() -> Result<()> {
+        let resolution = Resolution::new(
+            "https://hub.docker.com/_/redis".to_string(),
+            Strategy::DockerHub,
+        )
+        .with_input("redis", Some(&Parser::parse_image("redis")?));
+
+        assert_eq!(resolution.registry.as_deref(), Some("docker.io"));
+        assert_eq!(
+            resolution.reference.map(|reference| reference.name),
+            Some("redis".to_string())
+        );
+        Ok(())
+    }
+}
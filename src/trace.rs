@@ -0,0 +1,105 @@
+use std::{fmt, sync::Mutex};
+
+/// Steps attempted while resolving an image, printed with `--explain`
+#[derive(Debug, Default)]
+pub struct Trace {
+    steps: Mutex<Vec<Step>>,
+}
+
+/// An attempted step and what came of it (e.g. the HTTP status or error)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub action: String,
+    pub outcome: String,
+}
+
+impl Trace {
+    pub fn record(&self, action: impl Into<String>, outcome: impl Into<String>) {
+        let step = Step {
+            action: action.into(),
+            outcome: outcome.into(),
+        };
+
+        if let Ok(mut steps) = self.steps.lock() {
+            steps.push(step);
+        }
+    }
+
+    /// Record a failed step with the whole chain of its error
+    pub fn error(&self, action: impl Into<String>, error: &anyhow::Error) {
+        self.record(action, format!("error: {error:#}"));
+    }
+
+    pub fn steps(&self) -> Vec<Step> {
+        self.steps
+            .lock()
+            .map(|steps| steps.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps = self.steps();
+        let width = steps.len().to_string().len();
+
+        writeln!(f, "Resolution trace:")?;
+        for (index, step) in steps.iter().enumerate() {
+            writeln!(
+                f,
+                "  {:>width$}. {}: {}",
+                index + 1,
+                step.action,
+                step.outcome
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, anyhow};
+
+    #[test]
+    fn test_record() {
+        let trace = Trace::default();
+        trace.record("GitHub repo owner/repo", "default branch main");
+
+        assert_eq!(
+            trace.steps(),
+            vec![Step {
+                action: "GitHub repo owner/repo".to_string(),
+                outcome: "default branch main".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_error() {
+        let trace = Trace::default();
+        let error = Err::<(), _>(anyhow!("404 Not Found"))
+            .context("Failed to fetch manifest")
+            .unwrap_err();
+        trace.error("registry labels", &error);
+
+        assert_eq!(
+            trace.steps()[0].outcome,
+            "error: Failed to fetch manifest: 404 Not Found"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let trace = Trace::default();
+        for step in 1..=10 {
+            trace.record(format!("step {step}"), "missed");
+        }
+
+        let display = trace.to_string();
+        assert!(display.starts_with("Resolution trace:\n   1. step 1: missed\n"));
+        assert!(display.ends_with("  10. step 10: missed\n"));
+    }
+}
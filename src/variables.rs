@@ -0,0 +1,183 @@
+use anyhow::{Result, anyhow, bail};
+use std::collections::HashMap;
+
+/// Variables of a Dockerfile, expanded like BuildKit does
+pub const DOCKERFILE: Syntax = Syntax {
+    escape: false,
+    bare: true,
+    keep_unset: false,
+};
+
+/// Variables of a compose file, `$$` is a literal `$`
+pub const COMPOSE: Syntax = Syntax {
+    escape: true,
+    bare: true,
+    keep_unset: false,
+};
+
+/// Variables of a bake file, other `${...}` expressions are left to bake
+pub const BAKE: Syntax = Syntax {
+    escape: false,
+    bare: false,
+    keep_unset: true,
+};
+
+/// How a format writes variables
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Syntax {
+    /// `$$` is a literal `$`
+    pub escape: bool,
+    /// `$NAME` is a variable as well as `${NAME}`
+    pub bare: bool,
+    /// Variables which are not set are kept as written instead of expanding to nothing
+    pub keep_unset: bool,
+}
+
+/// Substitute `$NAME` and `${NAME}` with the modifiers of shells
+///
+/// `${NAME:-default}` and `${NAME-default}` fall back when unset (or empty with `:`),
+/// `${NAME:+alternative}` and `${NAME+alternative}` replace a set variable and
+/// `${NAME:?error}` and `${NAME?error}` fail when unset.
+pub fn expand(value: &str, variables: &HashMap<String, String>, syntax: Syntax) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        if syntax.escape
+            && let Some(after) = after.strip_prefix('$')
+        {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(braced) = after.strip_prefix('{') {
+            let end = closing(braced).ok_or_else(|| anyhow!("Unclosed variable in {value}"))?;
+            expanded.push_str(&substitute(&braced[..end], variables, syntax)?);
+            rest = &braced[end + 1..];
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            match variables.get(&after[..end]) {
+                Some(variable) if syntax.bare && end > 0 => expanded.push_str(variable),
+                None if syntax.bare && end > 0 && !syntax.keep_unset => {}
+                _ => expanded.push_str(&rest[start..start + 1 + end]),
+            }
+            rest = &after[end..];
+        }
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// Index of the brace closing a variable, defaults can hold variables themselves
+fn closing(braced: &str) -> Option<usize> {
+    let mut depth = 0;
+
+    for (index, c) in braced.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(index),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Substitute the expression between the braces of a variable
+fn substitute(
+    expression: &str,
+    variables: &HashMap<String, String>,
+    syntax: Syntax,
+) -> Result<String> {
+    let end = expression
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(expression.len());
+    let (name, modifier) = expression.split_at(end);
+
+    let value = variables.get(name);
+    if syntax.keep_unset && (value.is_none() || end == 0) {
+        return Ok(format!("${{{expression}}}"));
+    }
+
+    let set = value.is_some();
+    let filled = value.is_some_and(|value| !value.is_empty());
+    let value = value.cloned().unwrap_or_default();
+
+    if modifier.is_empty() {
+        return Ok(value);
+    }
+
+    // A colon also treats an empty variable as unset
+    let (present, modifier) = match modifier.strip_prefix(':') {
+        Some(modifier) => (filled, modifier),
+        None => (set, modifier),
+    };
+    let mut operator = modifier.chars();
+    let (operator, word) = (operator.next(), operator.as_str());
+
+    match operator {
+        Some('-') if present => Ok(value),
+        Some('-') => expand(word, variables, syntax),
+        Some('+') if present => expand(word, variables, syntax),
+        Some('+') => Ok(String::new()),
+        Some('?') if present => Ok(value),
+        Some('?') => bail!("{name}: {}", expand(word, variables, syntax)?),
+        _ => bail!("Invalid variable ${{{expression}}}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand() -> Result<()> {
+        let variables = variables(&[("NAME", "web"), ("EMPTY", "")]);
+        let expand = |value: &str| expand(value, &variables, COMPOSE);
+
+        assert_eq!(expand("$NAME/${NAME}")?, "web/web");
+        assert_eq!(expand("${EMPTY:-default}|${EMPTY-default}")?, "default|");
+        assert_eq!(expand("${MISSING-default}")?, "default");
+        assert_eq!(expand("${NAME:+set}${EMPTY:+set}${EMPTY+set}")?, "setset");
+        assert_eq!(expand("${MISSING:-${NAME}:1}")?, "web:1");
+        assert_eq!(expand("$MISSING-$")?, "-$");
+        assert!(expand("${MISSING:?must be set}").is_err());
+        assert!(expand("${NAME").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_escape() -> Result<()> {
+        let variables = variables(&[("NAME", "web")]);
+
+        assert_eq!(expand("$$NAME", &variables, COMPOSE)?, "$NAME");
+        assert_eq!(expand("$$NAME", &variables, DOCKERFILE)?, "$web");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_keep_unset() -> Result<()> {
+        let variables = variables(&[("TAG", "1.0")]);
+        let expand = |value: &str| expand(value, &variables, BAKE);
+
+        assert_eq!(expand("owner/app:${TAG}")?, "owner/app:1.0");
+        assert_eq!(expand("$TAG-${MISSING}")?, "$TAG-${MISSING}");
+        assert_eq!(expand("${timestamp()}")?, "${timestamp()}");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,764 @@
+use crate::{
+    discovery::Discovery,
+    parser::{ImageReference, Parser},
+    yaml,
+};
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Directory of the GitHub Actions workflows in a repository
+pub const WORKFLOWS_DIRECTORY: &str = ".github/workflows/";
+
+/// Stands in for unknown path components only to parse the rest of a pushed image
+const ANY_COMPONENT: &str = "any";
+
+const BUILD_PUSH_ACTION: &str = "docker/build-push-action@";
+const METADATA_ACTION: &str = "docker/metadata-action@";
+
+/// Prefix of steps which run a container image instead of an action
+const DOCKER_SCHEME: &str = "docker://";
+
+pub struct Workflow;
+
+/// A `docker/build-push-action` step with the images it pushes
+#[derive(Debug, PartialEq)]
+pub struct Build {
+    pub file: Option<String>,
+    pub context: Option<String>,
+    pub images: Vec<Pushed>,
+}
+
+/// Part of a workflow value, `${{ }}` expressions are unknown outside of a workflow run
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Literal(String),
+    Unknown,
+}
+
+/// The repository of an image a workflow pushes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pushed {
+    pub registry: Option<String>,
+    /// Path components, unknown ones (e.g. `${{ secrets.USER }}`) match any component
+    pub path: Vec<Segment>,
+}
+
+/// An image a workflow runs with where it is used (e.g. "ci.yml/test/services/redis")
+#[derive(Debug, PartialEq)]
+pub struct Usage {
+    pub location: String,
+    pub image: String,
+}
+
+impl Build {
+    /// Path of the Dockerfile relative to the repository root
+    ///
+    /// `file` is relative to the workspace while the default is the Dockerfile in the context.
+    pub fn dockerfile(&self) -> String {
+        match &self.file {
+            Some(file) => Discovery::join("", file),
+            None => Discovery::join(self.context.as_deref().unwrap_or("."), "Dockerfile"),
+        }
+    }
+
+    /// Check if any of the pushed images is the repository of the reference
+    pub fn pushes(&self, image: &ImageReference) -> bool {
+        self.images.iter().any(|pushed| pushed.matches(image))
+    }
+}
+
+impl Pushed {
+    /// Parse the repository of an image whose tag and path components may be unknown
+    pub fn parse(image: &[Segment]) -> Option<Self> {
+        let mut components: Vec<Vec<Segment>> = vec![vec![]];
+        for segment in image {
+            match segment {
+                Segment::Literal(text) => {
+                    for (index, part) in text.split('/').enumerate() {
+                        if index > 0 {
+                            components.push(vec![]);
+                        }
+                        Workflow::push(components.last_mut()?, Segment::Literal(part.to_string()));
+                    }
+                }
+                Segment::Unknown => components.last_mut()?.push(Segment::Unknown),
+            }
+        }
+
+        // The tag or digest of the last component does not name the repository
+        let last = components.last_mut()?;
+        if let Some(index) = last.iter().position(
+            |segment| matches!(segment, Segment::Literal(text) if text.contains([':', '@'])),
+        ) {
+            last.truncate(index + 1);
+            if let Some(Segment::Literal(text)) = last.last_mut() {
+                text.truncate(text.find([':', '@'])?);
+            }
+        }
+
+        let components: Vec<Option<String>> = components
+            .iter()
+            .map(|component| Workflow::known(component))
+            .collect();
+        let name = components
+            .iter()
+            .map(|component| component.as_deref().unwrap_or(ANY_COMPONENT))
+            .collect::<Vec<_>>()
+            .join("/");
+        let image = Parser::parse_image(&name).ok()?;
+
+        // The path is the end of the components, after the registry or behind "library"
+        let length = image.path.len();
+        let unknown = |index: usize| {
+            (components.len() + index)
+                .checked_sub(length)
+                .is_some_and(|index| components[index].is_none())
+        };
+
+        Some(Self {
+            registry: image.registry,
+            path: image
+                .path
+                .into_iter()
+                .enumerate()
+                .map(|(index, component)| {
+                    if unknown(index) {
+                        Segment::Unknown
+                    } else {
+                        Segment::Literal(component)
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Check if the pushed image is the repository of the reference
+    pub fn matches(&self, image: &ImageReference) -> bool {
+        self.registry == image.registry
+            && self.path.len() == image.path.len()
+            && self
+                .path
+                .iter()
+                .zip(&image.path)
+                .all(|(pushed, path)| match pushed {
+                    Segment::Literal(component) => component == path,
+                    Segment::Unknown => true,
+                })
+    }
+}
+
+impl Workflow {
+    /// Check if a path is a workflow file
+    pub fn workflow(path: &str) -> bool {
+        path.strip_prefix(WORKFLOWS_DIRECTORY).is_some_and(|file| {
+            !file.contains('/') && (file.ends_with(".yml") || file.ends_with(".yaml"))
+        })
+    }
+
+    /// Parse the image builds of a workflow of the repository (e.g. "owner/repo")
+    pub fn builds(workflow: &str, repository: &str) -> Result<Vec<Build>> {
+        let workflow: Value = serde_yaml::from_str(workflow)?;
+
+        let Some(jobs) = workflow.get("jobs").and_then(Value::as_mapping) else {
+            return Ok(vec![]);
+        };
+
+        let mut builds = vec![];
+
+        for job in jobs.values() {
+            let mut env = Self::env(workflow.get("env"));
+            env.extend(Self::env(job.get("env")));
+
+            let steps = job
+                .get("steps")
+                .and_then(Value::as_sequence)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            let expand = |value: &str| Self::expand(value, &env, repository);
+
+            // Images of metadata-action steps by step id, tags are usually taken from their outputs
+            let metadata: Vec<(Option<&str>, Vec<Pushed>)> = steps
+                .iter()
+                .filter(|step| Self::uses(step, METADATA_ACTION))
+                .map(|step| {
+                    let images = Self::input(step, "images")
+                        .map(|images| Self::metadata_images(&expand(&images)))
+                        .unwrap_or_default();
+
+                    (step.get("id").and_then(Value::as_str), images)
+                })
+                .collect();
+
+            for step in steps
+                .iter()
+                .filter(|step| Self::uses(step, BUILD_PUSH_ACTION))
+            {
+                let tags = Self::input(step, "tags").unwrap_or_default();
+
+                // Tags which are only an expression (e.g. the outputs of another step) name no image
+                let mut images: Vec<Pushed> = Self::list(&expand(&tags))
+                    .into_iter()
+                    .filter(|tag| {
+                        Self::known(tag).is_some()
+                            || tag.iter().any(|segment| {
+                                matches!(segment, Segment::Literal(text) if text.contains('/'))
+                            })
+                    })
+                    .filter_map(|tag| Pushed::parse(&tag))
+                    .collect();
+
+                // Tags from `${{ steps.<id>.outputs.tags }}` are the images of that step
+                for (id, metadata_images) in &metadata {
+                    let referenced = id.is_some_and(|id| tags.contains(&format!("steps.{id}.")));
+                    if referenced || (tags.trim().is_empty() && metadata.len() == 1) {
+                        images.extend(metadata_images.iter().cloned());
+                    }
+                }
+
+                // Dockerfiles named by expressions are unknown outside of a workflow run
+                let file = Self::input(step, "file").map(|file| Self::known(&expand(&file)));
+                let context =
+                    Self::input(step, "context").map(|context| Self::known(&expand(&context)));
+                if file.as_ref().is_some_and(Option::is_none)
+                    || context.as_ref().is_some_and(Option::is_none)
+                {
+                    continue;
+                }
+
+                builds.push(Build {
+                    file: file.flatten(),
+                    context: context.flatten(),
+                    images,
+                });
+            }
+        }
+
+        Ok(builds)
+    }
+
+    /// Read the images of a workflow file or of the workflow files of a directory
+    ///
+    /// Like GitHub only the top level of the directory holds workflows, subdirectories are not
+    /// read.
+    pub fn read(path: &Path) -> Result<Vec<Usage>> {
+        let mut usages = vec![];
+        for file in yaml::files(path, false)? {
+            let workflow = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let name = file
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+
+            for usage in Self::images(&workflow)
+                .with_context(|| format!("Failed to parse {}", file.display()))?
+            {
+                usages.push(Usage {
+                    location: format!("{name}/{}", usage.location),
+                    ..usage
+                });
+            }
+        }
+
+        Ok(usages)
+    }
+
+    /// Parse the images jobs run in (`container`), next to (`services`) and as steps (`docker://`)
+    pub fn images(workflow: &str) -> Result<Vec<Usage>> {
+        let workflow: Value = serde_yaml::from_str(workflow)?;
+
+        let Some(jobs) = workflow.get("jobs").and_then(Value::as_mapping) else {
+            return Ok(vec![]);
+        };
+
+        let mut usages = vec![];
+        let mut usage = |location: String, image: &str| {
+            usages.push(Usage {
+                location,
+                image: image.to_string(),
+            })
+        };
+
+        for (id, job) in jobs {
+            let id = id.as_str().unwrap_or_default();
+
+            // A container is either the image or its configuration
+            let container = job.get("container");
+            if let Some(image) = container
+                .and_then(Value::as_str)
+                .or_else(|| container?.get("image")?.as_str())
+            {
+                usage(format!("{id}/container"), image);
+            }
+
+            for (name, service) in job
+                .get("services")
+                .and_then(Value::as_mapping)
+                .into_iter()
+                .flatten()
+            {
+                if let Some(image) = service.get("image").and_then(Value::as_str) {
+                    let name = name.as_str().unwrap_or_default();
+                    usage(format!("{id}/services/{name}"), image);
+                }
+            }
+
+            let steps = job
+                .get("steps")
+                .and_then(Value::as_sequence)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            for (index, step) in steps.iter().enumerate() {
+                if let Some(image) = step
+                    .get("uses")
+                    .and_then(Value::as_str)
+                    .and_then(|uses| uses.strip_prefix(DOCKER_SCHEME))
+                {
+                    // Steps are named by their id or their position
+                    let step = match step.get("id").and_then(Value::as_str) {
+                        Some(step) => step.to_string(),
+                        None => (index + 1).to_string(),
+                    };
+                    usage(format!("{id}/steps/{step}"), image);
+                }
+            }
+        }
+
+        Ok(usages)
+    }
+
+    fn uses(step: &Value, action: &str) -> bool {
+        step.get("uses")
+            .and_then(Value::as_str)
+            .is_some_and(|uses| uses.starts_with(action))
+    }
+
+    /// Read an input of a step which may be a string or a list
+    fn input(step: &Value, name: &str) -> Option<String> {
+        match step.get("with")?.get(name)? {
+            Value::String(value) => Some(value.clone()),
+            Value::Sequence(values) => Some(
+                values
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            Value::Bool(value) => Some(value.to_string()),
+            Value::Number(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    fn env(env: Option<&Value>) -> HashMap<String, String> {
+        env.and_then(Value::as_mapping)
+            .map(Mapping::iter)
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, value)| {
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    Value::Number(value) => value.to_string(),
+                    Value::Bool(value) => value.to_string(),
+                    _ => return None,
+                };
+                Some((key.as_str()?.to_string(), value))
+            })
+            .collect()
+    }
+
+    /// Replace `${{ ... }}` expressions with their values where they are known statically
+    fn expand(value: &str, env: &HashMap<String, String>, repository: &str) -> Vec<Segment> {
+        let mut segments = vec![];
+        let mut rest = value;
+
+        while let Some(start) = rest.find("${{") {
+            Self::push(&mut segments, Segment::Literal(rest[..start].to_string()));
+
+            let Some(end) = rest[start..].find("}}") else {
+                rest = &rest[start..];
+                break;
+            };
+            let expression = rest[start + 3..start + end].trim();
+            rest = &rest[start + end + 2..];
+
+            let owner = repository.split('/').next().unwrap_or_default();
+            let name = repository.rsplit('/').next().unwrap_or_default();
+
+            let replacement = match expression {
+                "github.repository" => vec![Segment::Literal(repository.to_ascii_lowercase())],
+                "github.repository_owner" => vec![Segment::Literal(owner.to_ascii_lowercase())],
+                "github.event.repository.name" => {
+                    vec![Segment::Literal(name.to_ascii_lowercase())]
+                }
+                expression => expression
+                    .strip_prefix("env.")
+                    .and_then(|key| env.get(key))
+                    .map(|value| Self::expand(value, &HashMap::new(), repository))
+                    .unwrap_or_else(|| vec![Segment::Unknown]),
+            };
+
+            for segment in replacement {
+                Self::push(&mut segments, segment);
+            }
+        }
+
+        Self::push(&mut segments, Segment::Literal(rest.to_string()));
+        segments
+    }
+
+    /// Append a segment, joining consecutive literals and skipping empty ones
+    fn push(segments: &mut Vec<Segment>, segment: Segment) {
+        match (segments.last_mut(), segment) {
+            (_, Segment::Literal(text)) if text.is_empty() => {}
+            (Some(Segment::Literal(last)), Segment::Literal(text)) => last.push_str(&text),
+            (_, segment) => segments.push(segment),
+        }
+    }
+
+    /// The value of the segments if none of them is unknown
+    fn known(segments: &[Segment]) -> Option<String> {
+        segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => Some(text.as_str()),
+                Segment::Unknown => None,
+            })
+            .collect()
+    }
+
+    /// Split the literals of the segments at the separators and trim whitespace around the items
+    fn split(segments: &[Segment], separators: &[char]) -> Vec<Vec<Segment>> {
+        let mut items = vec![vec![]];
+
+        for segment in segments {
+            match segment {
+                Segment::Literal(text) => {
+                    for (index, part) in text.split(separators).enumerate() {
+                        if index > 0 {
+                            items.push(vec![]);
+                        }
+                        if let Some(item) = items.last_mut() {
+                            Self::push(item, Segment::Literal(part.to_string()));
+                        }
+                    }
+                }
+                Segment::Unknown => {
+                    if let Some(item) = items.last_mut() {
+                        item.push(Segment::Unknown);
+                    }
+                }
+            }
+        }
+
+        for item in &mut items {
+            if let Some(Segment::Literal(text)) = item.first_mut() {
+                *text = text.trim_start().to_string();
+            }
+            if let Some(Segment::Literal(text)) = item.last_mut() {
+                *text = text.trim_end().to_string();
+            }
+            item.retain(|segment| segment != &Segment::Literal(String::new()));
+        }
+
+        items
+    }
+
+    /// Split a newline or comma separated list
+    fn list(value: &[Segment]) -> Vec<Vec<Segment>> {
+        Self::split(value, &['\n', ','])
+            .into_iter()
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+
+    /// Image names of a metadata-action `images` input (e.g. "name=ghcr.io/owner/repo,enable=true")
+    fn metadata_images(images: &[Segment]) -> Vec<Pushed> {
+        Self::split(images, &['\n'])
+            .into_iter()
+            .filter_map(|line| {
+                let mut name = Self::split(&line, &[',']).into_iter().next()?;
+                if let Some(Segment::Literal(text)) = name.first_mut()
+                    && let Some(stripped) = text.strip_prefix("name=")
+                {
+                    *text = stripped.to_string();
+                }
+                Pushed::parse(&name)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPOSITORY: &str = "Owner/repo";
+
+    fn literal(text: &str) -> Segment {
+        Segment::Literal(text.to_string())
+    }
+
+    fn pushed(registry: Option<&str>, path: Vec<Segment>) -> Pushed {
+        Pushed {
+            registry: registry.map(String::from),
+            path,
+        }
+    }
+
+    const WORKFLOW: &str = r#"
+name: release
+
+env:
+  REGISTRY: ghcr.io
+
+jobs:
+  docker:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v4
+      - id: meta
+        uses: docker/metadata-action@v5
+        with:
+          images: |
+            name=${{ env.REGISTRY }}/${{ github.repository }},enable=true
+            ${{ secrets.DOCKERHUB_USER }}/repo
+      - uses: docker/build-push-action@v6
+        with:
+          context: .
+          file: ./docker/Dockerfile.release
+          push: true
+          tags: ${{ steps.meta.outputs.tags }}
+
+  worker:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: docker/build-push-action@v6
+        with:
+          context: services/worker
+          tags: |
+            quay.io/owner/worker:latest
+            quay.io/owner/worker:${{ github.sha }}
+"#;
+
+    mod public {
+        use super::*;
+
+        #[test]
+        fn test_workflow() {
+            assert!(Workflow::workflow(".github/workflows/release.yml"));
+            assert!(Workflow::workflow(".github/workflows/ci.yaml"));
+            assert!(!Workflow::workflow(".github/workflows/scripts/build.yml"));
+            assert!(!Workflow::workflow("workflows/release.yml"));
+        }
+
+        #[test]
+        fn test_builds() -> Result<()> {
+            assert_eq!(
+                Workflow::builds(WORKFLOW, REPOSITORY)?,
+                vec![
+                    Build {
+                        file: Some("./docker/Dockerfile.release".to_string()),
+                        context: Some(".".to_string()),
+                        images: vec![
+                            pushed(Some("ghcr.io"), vec![literal("owner"), literal("repo")]),
+                            pushed(None, vec![Segment::Unknown, literal("repo")]),
+                        ],
+                    },
+                    Build {
+                        file: None,
+                        context: Some("services/worker".to_string()),
+                        images: vec![
+                            pushed(Some("quay.io"), vec![literal("owner"), literal("worker")]),
+                            pushed(Some("quay.io"), vec![literal("owner"), literal("worker")]),
+                        ],
+                    },
+                ]
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_builds_without_jobs() -> Result<()> {
+            assert!(Workflow::builds("name: empty", REPOSITORY)?.is_empty());
+            Ok(())
+        }
+
+        #[test]
+        fn test_images() -> Result<()> {
+            let workflow = r#"
+jobs:
+  test:
+    runs-on: ubuntu-latest
+    container: node:20
+    services:
+      redis:
+        image: redis:7
+      postgres:
+        image: postgres:16
+        ports: ["5432:5432"]
+    steps:
+      - uses: actions/checkout@v4
+      - uses: docker://alpine:3.20
+      - id: lint
+        uses: docker://ghcr.io/owner/linter:1.0
+  build:
+    container:
+      image: ghcr.io/owner/builder:latest
+      options: --user root
+"#;
+
+            let usages = Workflow::images(workflow)?;
+            let usages: Vec<(&str, &str)> = usages
+                .iter()
+                .map(|usage| (usage.location.as_str(), usage.image.as_str()))
+                .collect();
+            assert_eq!(
+                usages,
+                vec![
+                    ("test/container", "node:20"),
+                    ("test/services/redis", "redis:7"),
+                    ("test/services/postgres", "postgres:16"),
+                    ("test/steps/2", "alpine:3.20"),
+                    ("test/steps/lint", "ghcr.io/owner/linter:1.0"),
+                    ("build/container", "ghcr.io/owner/builder:latest"),
+                ]
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_read_directory() -> Result<()> {
+            let directory = tempfile::tempdir()?;
+            fs::write(
+                directory.path().join("ci.yml"),
+                "jobs:\n  test:\n    container: node:20\n",
+            )?;
+            fs::write(directory.path().join("notes.txt"), "container: node")?;
+            // Templates are not workflows and may not even parse as one
+            fs::create_dir(directory.path().join("templates"))?;
+            fs::write(
+                directory.path().join("templates/job.yml"),
+                "---\na: 1\n---\nb: 2\n",
+            )?;
+
+            assert_eq!(
+                Workflow::read(directory.path())?,
+                vec![Usage {
+                    location: "ci.yml/test/container".to_string(),
+                    image: "node:20".to_string(),
+                }]
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_dockerfile() -> Result<()> {
+            let builds = Workflow::builds(WORKFLOW, REPOSITORY)?;
+
+            assert_eq!(builds[0].dockerfile(), "docker/Dockerfile.release");
+            assert_eq!(builds[1].dockerfile(), "services/worker/Dockerfile");
+            Ok(())
+        }
+
+        #[test]
+        fn test_pushes() -> Result<()> {
+            let builds = Workflow::builds(WORKFLOW, REPOSITORY)?;
+
+            assert!(builds[0].pushes(&Parser::parse_image("ghcr.io/owner/repo:1.0")?));
+            assert!(builds[0].pushes(&Parser::parse_image("someone/repo")?));
+            assert!(!builds[0].pushes(&Parser::parse_image("quay.io/owner/worker")?));
+            assert!(builds[1].pushes(&Parser::parse_image("quay.io/owner/worker:2.0")?));
+            Ok(())
+        }
+
+        #[test]
+        fn test_pushed_parse() -> Result<()> {
+            // A known tag or an unknown one is not part of the repository
+            assert_eq!(
+                Pushed::parse(&[
+                    literal("localhost:5000/"),
+                    Segment::Unknown,
+                    literal("/app:"),
+                    Segment::Unknown,
+                ]),
+                Some(pushed(
+                    Some("localhost:5000"),
+                    vec![Segment::Unknown, literal("app")]
+                ))
+            );
+            assert_eq!(
+                Pushed::parse(&[Segment::Unknown]),
+                Some(pushed(None, vec![literal("library"), Segment::Unknown]))
+            );
+            assert_eq!(Pushed::parse(&[literal("Invalid Image")]), None);
+            Ok(())
+        }
+
+        #[test]
+        fn test_pushed_matches_literal() -> Result<()> {
+            // Components are only unknown because of an expression, never because of their name
+            let pushed = pushed(None, vec![literal("any"), literal("repo")]);
+
+            assert!(pushed.matches(&Parser::parse_image("any/repo")?));
+            assert!(!pushed.matches(&Parser::parse_image("owner/repo")?));
+            Ok(())
+        }
+    }
+
+    mod private {
+        use super::*;
+
+        #[test]
+        fn test_expand() {
+            let env =
+                HashMap::from([("IMAGE".to_string(), "${{ github.repository }}".to_string())]);
+
+            assert_eq!(
+                Workflow::expand(
+                    "ghcr.io/${{ env.IMAGE }}:${{ github.sha }}",
+                    &env,
+                    REPOSITORY
+                ),
+                vec![literal("ghcr.io/owner/repo:"), Segment::Unknown]
+            );
+        }
+
+        #[test]
+        fn test_expand_unterminated() {
+            assert_eq!(
+                Workflow::expand("owner/${{ env.IMAGE", &HashMap::new(), REPOSITORY),
+                vec![literal("owner/${{ env.IMAGE")]
+            );
+        }
+
+        #[test]
+        fn test_metadata_images() {
+            assert_eq!(
+                Workflow::metadata_images(&[
+                    literal("name=ghcr.io/owner/repo,enable=true\n  "),
+                    Segment::Unknown,
+                    literal("/repo\n"),
+                ]),
+                vec![
+                    pushed(Some("ghcr.io"), vec![literal("owner"), literal("repo")]),
+                    pushed(None, vec![Segment::Unknown, literal("repo")]),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_list() {
+            assert_eq!(
+                Workflow::list(&[literal(" a/b:1, c/"), Segment::Unknown, literal(" \n\n")]),
+                vec![
+                    vec![literal("a/b:1")],
+                    vec![literal("c/"), Segment::Unknown]
+                ]
+            );
+        }
+    }
+}
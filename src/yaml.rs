@@ -0,0 +1,41 @@
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The path itself or the YAML files of the directory, sorted
+///
+/// Subdirectories are only read when `recursive`, symlinked directories never are so a link
+/// back to a parent cannot loop.
+pub fn files(path: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut entries = fs::read_dir(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.path(), entry.file_type()?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut files = vec![];
+    for (entry, file_type) in entries {
+        if file_type.is_dir() {
+            if recursive {
+                files.extend(self::files(&entry, recursive)?);
+            }
+        } else if !entry.is_dir()
+            && entry
+                .extension()
+                .is_some_and(|extension| extension == "yaml" || extension == "yml")
+        {
+            files.push(entry);
+        }
+    }
+
+    Ok(files)
+}